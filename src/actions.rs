@@ -0,0 +1,290 @@
+//! Everything the player does that changes the game. Input and UI systems send these as events
+//! instead of changing the game themselves, so that they're applied at the start of a tick and
+//! can be recorded and replayed.
+
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioChannel, AudioControl};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{DrumsChannel, VolumeSettings},
+    enemies::Enemy,
+    gameplay::{
+        tower_offer, ActiveWave, GameManager, WaveState, AUTO_START_SECONDS, EARLY_CALL_BONUS,
+    },
+    grid::Map,
+    rng::GameRng,
+    state::loading::GameAssets,
+    tower::{
+        debuffs::SpeedUpPoint,
+        laser::{spawn_laser_beam, Laser},
+        spawn_tier_indicator, spawn_tower, TierIndicator, Tower, TowerPlaced, TowerRemoved,
+    },
+    ui::{
+        inventory::Inventory,
+        statusbar::GameSpeed,
+        tower_options::{present_tower_options, TowerOptionsRoot},
+        UiData, UiState, UiStateResource,
+    },
+    waves::WaveList,
+};
+
+/// How much faster the game runs at fast speed.
+pub const FAST_SPEED: f32 = 3.0;
+
+#[derive(Event, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlayerAction {
+    /// Take one of the towers offered after a wave.
+    PickTower {
+        index: usize,
+    },
+    /// Place a tower from the inventory.
+    PlaceTower {
+        index: usize,
+        pos: (i8, i8),
+    },
+    /// Turn the laser at the given position clockwise.
+    RotateLaser {
+        pos: (i8, i8),
+    },
+    /// Spend upgrade points on raising the tower at the given position a level. Only allowed
+    /// between waves.
+    UpgradeTower {
+        pos: (i8, i8),
+    },
+    /// Remove the tower at the given position, along with its debuffs.
+    SellTower {
+        pos: (i8, i8),
+    },
+    /// Start the next wave, or call it early if there's already a wave in play.
+    StartWave,
+    /// Turn the countdown that starts the next wave automatically on or off.
+    ToggleAutoStart,
+    SetSpeed {
+        fast: bool,
+    },
+    /// Swap in waves edited on disk partway through a run. Sent by `waves::reload_waves` rather
+    /// than the player, so the edit is recorded and replayed along with everything else.
+    LoadWaves {
+        waves: WaveList,
+    },
+}
+
+pub fn apply_tower_actions(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    mut ui_state: ResMut<UiStateResource>,
+    mut inventory: ResMut<Inventory>,
+    options_root: Query<Entity, With<TowerOptionsRoot>>,
+    mut lasers: Query<(Entity, &mut Transform, &mut Laser, &Children)>,
+    mut tier_indicators: Query<&mut Transform, (With<TierIndicator>, Without<Laser>)>,
+    mut map: ResMut<Map>,
+    game_assets: Res<GameAssets>,
+    mut event_writer: EventWriter<TowerPlaced>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut game_manager: ResMut<GameManager>,
+    mut rng: ResMut<GameRng>,
+) {
+    for action in actions.read() {
+        match *action {
+            PlayerAction::PickTower { index } => {
+                let UiState::PickingTower(ref mut options) = ui_state.state else {
+                    continue;
+                };
+                if index < options.len() {
+                    let tower = options.remove(index);
+                    inventory.towers.push(tower);
+                }
+                for entity in options_root.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                ui_state.state = UiState::Normal;
+                // Make up for the offers of waves that were called early
+                if game_manager.owed_offers > 0 {
+                    game_manager.owed_offers -= 1;
+                    let options = tower_offer(false, &mut rng);
+                    present_tower_options(commands.reborrow(), game_assets.font.clone(), &options);
+                    ui_state.state = UiState::PickingTower(options);
+                }
+            }
+            PlayerAction::PlaceTower { index, pos } => {
+                if index < inventory.towers.len() && map.is_valid_placement(pos) {
+                    let tower = inventory.towers.remove(index);
+                    spawn_tower(
+                        tower,
+                        &mut commands,
+                        pos,
+                        &game_assets,
+                        &mut event_writer,
+                        &mut meshes,
+                        &mut materials,
+                        &mut map,
+                    );
+                }
+            }
+            PlayerAction::RotateLaser { pos } => {
+                let Some(entity) = map.placements.get(&pos) else {
+                    continue;
+                };
+                let Ok((entity, mut transform, mut laser, children)) = lasers.get_mut(*entity)
+                else {
+                    continue;
+                };
+                // Change laser direction
+                transform.rotation *= Quat::from_rotation_z(-std::f32::consts::PI / 2.0);
+                laser.toggle_direction();
+                // Remove and redraw beam, keeping the tier pips upright
+                for child in children.iter() {
+                    if let Ok(mut tier_transform) = tier_indicators.get_mut(*child) {
+                        tier_transform.rotation = transform.rotation.inverse();
+                    } else {
+                        commands.entity(*child).despawn_recursive();
+                    }
+                }
+                commands.entity(entity).with_children(|parent| {
+                    spawn_laser_beam(
+                        parent,
+                        pos,
+                        laser.direction,
+                        &mut meshes,
+                        &mut materials,
+                        &map,
+                    )
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn apply_upgrade_actions(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    mut game_manager: ResMut<GameManager>,
+    mut towers: Query<(&mut Tower, &Transform, Option<&Children>)>,
+    tier_indicators: Query<(), With<TierIndicator>>,
+    map: Res<Map>,
+    mut ui_data: ResMut<UiData>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for action in actions.read() {
+        let PlayerAction::UpgradeTower { pos } = *action else {
+            continue;
+        };
+        if !matches!(game_manager.wave_state, WaveState::Waiting) {
+            continue;
+        }
+        let Some(&entity) = map.placements.get(&pos) else {
+            continue;
+        };
+        let Ok((mut tower, transform, children)) = towers.get_mut(entity) else {
+            continue;
+        };
+        let Some(cost) = tower.upgrade_cost() else {
+            continue;
+        };
+        if game_manager.upgrade_points < cost {
+            continue;
+        }
+        game_manager.upgrade_points -= cost;
+        tower.upgrade();
+
+        // Missile launchers start out without any children
+        for child in children.into_iter().flat_map(|children| children.iter()) {
+            if tier_indicators.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        commands.entity(entity).with_children(|parent| {
+            spawn_tier_indicator(
+                parent,
+                tower.level,
+                transform.rotation,
+                &mut meshes,
+                &mut materials,
+            );
+        });
+        // Show the new stats
+        ui_data.set_changed();
+    }
+}
+
+pub fn apply_sell_actions(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    mut game_manager: ResMut<GameManager>,
+    towers: Query<&Tower>,
+    speed_up_points: Query<(Entity, &Transform), With<SpeedUpPoint>>,
+    mut map: ResMut<Map>,
+    mut event_writer: EventWriter<TowerRemoved>,
+) {
+    for action in actions.read() {
+        let PlayerAction::SellTower { pos } = *action else {
+            continue;
+        };
+        let Some(entity) = map.placements.remove(&pos) else {
+            continue;
+        };
+        if let Ok(tower) = towers.get(entity) {
+            game_manager.upgrade_points += tower.sell_refund();
+        }
+        commands.entity(entity).despawn_recursive();
+
+        // Speed up points are only ever placed on the tile of the tower they belong to
+        for (point, transform) in speed_up_points.iter() {
+            if Map::get_grid_pos(transform.translation.truncate()) == pos {
+                commands.entity(point).despawn();
+            }
+        }
+        event_writer.send(TowerRemoved { grid_pos: pos });
+    }
+}
+
+pub fn apply_game_actions(
+    mut actions: EventReader<PlayerAction>,
+    mut game_manager: ResMut<GameManager>,
+    enemies: Query<&Enemy>,
+    map: Res<Map>,
+    mut game_speed: ResMut<GameSpeed>,
+    mut time: ResMut<Time<Virtual>>,
+    drums_channel: Res<AudioChannel<DrumsChannel>>,
+    volume_settings: Res<VolumeSettings>,
+) {
+    for action in actions.read() {
+        match *action {
+            PlayerAction::StartWave => match game_manager.wave_state {
+                WaveState::Waiting => {
+                    drums_channel.set_volume(volume_settings.music_vol * 1.5);
+                    game_manager.start_wave(map.spawns.len());
+                }
+                WaveState::Spawning | WaveState::Finished => {
+                    // Call the next wave early, rewarding the player for the extra pressure
+                    let remaining = game_manager
+                        .active_waves
+                        .iter()
+                        .map(ActiveWave::remaining_points)
+                        .sum::<u32>()
+                        + enemies.iter().map(|e| e.variant.points()).sum::<u32>();
+                    game_manager.score += (remaining as f32 * EARLY_CALL_BONUS) as u32;
+                    game_manager.current_wave += 1;
+                    game_manager.called_early += 1;
+                    game_manager.start_wave(map.spawns.len());
+                }
+            },
+            PlayerAction::ToggleAutoStart => {
+                game_manager.countdown = match game_manager.countdown {
+                    Some(_) => None,
+                    None => Some(Timer::from_seconds(AUTO_START_SECONDS, TimerMode::Once)),
+                };
+            }
+            PlayerAction::SetSpeed { fast } => {
+                game_speed.0 = fast;
+                time.set_relative_speed(if fast { FAST_SPEED } else { 1.0 });
+            }
+            PlayerAction::LoadWaves { ref waves } => game_manager.load_waves(waves),
+            _ => {}
+        }
+    }
+}
@@ -2,7 +2,7 @@ use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioSource;
 
-#[derive(Resource, AssetCollection)]
+#[derive(Resource, AssetCollection, Default)]
 pub struct AudioAssets {
     #[asset(path = "audio/bgm-main.ogg")]
     pub bgm_main: Handle<AudioSource>,
@@ -0,0 +1,27 @@
+//! Checks a replay's score by playing it headlessly on the given map.
+//!
+//! Usage: `verify <replay> <map>`, where the map path is relative to the assets folder.
+
+use std::path::Path;
+
+use neon_breach::verify;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, replay, map] = &args[..] else {
+        eprintln!("Usage: verify <replay> <map>");
+        std::process::exit(2);
+    };
+    match verify::verify_file(Path::new(replay), Path::new("assets"), map) {
+        Ok(report) => {
+            println!("Verified");
+            println!("Seed: {}", report.seed);
+            println!("Wave reached: {}", report.wave_reached);
+            println!("Score: {}", report.score);
+        }
+        Err(error) => {
+            eprintln!("Verification failed: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
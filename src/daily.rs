@@ -0,0 +1,63 @@
+//! The daily challenge: everyone playing on the same day gets the same map, seed and tower
+//! offers, and their scores are ranked separately from normal runs.
+
+use bevy::utils::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The number of days since the Unix epoch, in UTC.
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+        / SECONDS_PER_DAY
+}
+
+/// The seed for the daily challenge on the given day.
+pub fn seed(day: u64) -> u64 {
+    // Spread consecutive days out so their seeds don't look related
+    day.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// The given day as a `YYYY-MM-DD` date.
+pub fn date(day: u64) -> String {
+    // Convert days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_starts_at_the_epoch() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(19722), "2023-12-31");
+        assert_eq!(date(19723), "2024-01-01");
+    }
+
+    #[test]
+    fn date_handles_leap_days() {
+        assert_eq!(date(19782), "2024-02-29");
+        assert_eq!(date(19783), "2024-03-01");
+        assert_eq!(date(11016), "2000-02-29");
+        // Centuries are only leap years when they divide by 400
+        assert_eq!(date(47540), "2100-02-28");
+        assert_eq!(date(47541), "2100-03-01");
+    }
+}
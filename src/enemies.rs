@@ -1,15 +1,19 @@
 use bevy::prelude::*;
 use bevy_kira_audio::{AudioChannel, AudioControl};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     audio::{AudioAssets, SoundChannel},
     gameplay::GameManager,
-    grid::Map,
-    tower::debuffs::SpeedUpPoint,
-    ui::constants::GREEN,
+    grid::{map_file::MapMode, path::follow_distances, Map},
+    tower::{
+        debuffs::{SpeedUpPoint, SPEED_UP_RADIUS},
+        TowerPlaced, TowerRemoved,
+    },
+    ui::constants::{BLUE, GREEN},
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EnemyVariant {
     Weak,
     Normal,
@@ -31,15 +35,60 @@ impl EnemyVariant {
             Self::UltraBoss => 50,
         }
     }
+
+    /// How much damage is taken off every hit.
+    pub fn armour(&self) -> f32 {
+        match self {
+            Self::Strong => 0.1,
+            Self::Boss => 0.15,
+            Self::StrongFast => 0.05,
+            Self::UltraBoss => 0.2,
+            _ => 0.0,
+        }
+    }
+
+    /// How much of its max health the enemy heals every second.
+    pub fn regeneration(&self) -> f32 {
+        match self {
+            Self::Boss => 0.01,
+            Self::StrongFast => 0.02,
+            Self::UltraBoss => 0.015,
+            _ => 0.0,
+        }
+    }
+
+    /// How big the enemy's shield is, compared to its max health.
+    pub fn shield(&self) -> f32 {
+        match self {
+            Self::Strong => 0.25,
+            Self::Boss => 0.25,
+            Self::UltraBoss => 0.4,
+            _ => 0.0,
+        }
+    }
 }
 
+/// How much of a hit always gets through armour, so every tower can hurt every enemy.
+const MIN_DAMAGE: f32 = 0.1;
+/// How many seconds a shield waits after a hit before it starts recharging.
+const SHIELD_RECHARGE_DELAY: f32 = 3.0;
+/// How much of a shield recharges every second.
+const SHIELD_RECHARGE_RATE: f32 = 0.2;
+
 #[derive(Debug, Component)]
 pub struct Enemy {
     pub variant: EnemyVariant,
     pub max_health: f32,
     pub current_health: f32,
+    pub max_shield: f32,
+    pub current_shield: f32,
+    /// How many seconds it's been since the enemy was last hit.
+    pub since_hit: f32,
     pub healthbar: Option<Entity>,
+    pub shieldbar: Option<Entity>,
     pub move_speed: f32,
+    /// The waypoints this enemy walks between to reach the exit.
+    pub path: Vec<(i8, i8)>,
     pub path_target: usize,
     pub current_grid_pos: (i8, i8),
     pub distance_travelled: f32,
@@ -48,80 +97,65 @@ impl Enemy {
     pub fn new(
         variant: EnemyVariant,
         grid_pos: (i8, i8),
+        path: Vec<(i8, i8)>,
         health_multiplier: f32,
         speed_multiplier: f32,
     ) -> Self {
-        match variant {
-            EnemyVariant::Weak => Self {
-                variant,
-                max_health: 2.0 * health_multiplier,
-                current_health: 2.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 15.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::Normal => Self {
-                variant,
-                max_health: 5.0 * health_multiplier,
-                current_health: 5.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 20.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::Fast => Self {
-                variant,
-                max_health: 5.0 * health_multiplier,
-                current_health: 5.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 40.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::Strong => Self {
-                variant,
-                max_health: 20.0 * health_multiplier,
-                current_health: 20.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 15.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::Boss => Self {
-                variant,
-                max_health: 100.0 * health_multiplier,
-                current_health: 100.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 10.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::StrongFast => Self {
-                variant,
-                max_health: 20.0 * health_multiplier,
-                current_health: 20.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 35.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
-            EnemyVariant::UltraBoss => Self {
-                variant,
-                max_health: 250.0 * health_multiplier,
-                current_health: 250.0 * health_multiplier,
-                healthbar: None,
-                move_speed: 10.0 * speed_multiplier,
-                path_target: 0,
-                current_grid_pos: grid_pos,
-                distance_travelled: 0.0,
-            },
+        let (health, move_speed) = match variant {
+            EnemyVariant::Weak => (2.0, 15.0),
+            EnemyVariant::Normal => (5.0, 20.0),
+            EnemyVariant::Fast => (5.0, 40.0),
+            EnemyVariant::Strong => (20.0, 15.0),
+            EnemyVariant::Boss => (100.0, 10.0),
+            EnemyVariant::StrongFast => (20.0, 35.0),
+            EnemyVariant::UltraBoss => (250.0, 10.0),
+        };
+        let max_health = health * health_multiplier;
+        let max_shield = max_health * variant.shield();
+        Self {
+            variant,
+            max_health,
+            current_health: max_health,
+            max_shield,
+            current_shield: max_shield,
+            since_hit: 0.0,
+            healthbar: None,
+            shieldbar: None,
+            move_speed: move_speed * speed_multiplier,
+            path,
+            path_target: 0,
+            current_grid_pos: grid_pos,
+            distance_travelled: 0.0,
+        }
+    }
+
+    /// Hit the enemy for `damage`. Armour takes a flat amount off every hit, and whatever is
+    /// left is taken by the shield before the enemy's health.
+    pub fn take_damage(&mut self, damage: f32) {
+        let mut damage = (damage - self.variant.armour()).max(damage * MIN_DAMAGE);
+        let absorbed = damage.min(self.current_shield);
+        self.current_shield -= absorbed;
+        damage -= absorbed;
+        self.current_health -= damage;
+        self.since_hit = 0.0;
+    }
+
+    /// Heal the enemy and recharge its shield for `delta` seconds, up to their maximums. Dead
+    /// enemies stay dead.
+    pub fn regenerate(&mut self, delta: f32) {
+        if self.current_health <= 0.0 {
+            return;
+        }
+        let regeneration = self.variant.regeneration();
+        if regeneration > 0.0 && self.current_health < self.max_health {
+            self.current_health =
+                (self.current_health + self.max_health * regeneration * delta).min(self.max_health);
+        }
+        self.since_hit += delta;
+        if self.since_hit >= SHIELD_RECHARGE_DELAY && self.current_shield < self.max_shield {
+            self.current_shield = (self.current_shield
+                + self.max_shield * SHIELD_RECHARGE_RATE * delta)
+                .min(self.max_shield);
         }
     }
 }
@@ -147,6 +181,55 @@ pub fn update_enemy_grid_pos(
     }
 }
 
+/// In maze mode, find every enemy a new way to the exit whenever a tower is placed or sold.
+pub fn reroute_enemies(
+    mut placed: EventReader<TowerPlaced>,
+    mut removed: EventReader<TowerRemoved>,
+    mut enemies: Query<(&mut Enemy, &Transform)>,
+    map: Res<Map>,
+) {
+    if placed.is_empty() && removed.is_empty() {
+        return;
+    }
+    placed.clear();
+    removed.clear();
+    if map.mode != MapMode::Maze {
+        return;
+    }
+    let distances = map.exit_distances(None);
+    for (mut enemy, transform) in enemies.iter_mut() {
+        let Some(target) = enemy.path.get(enemy.path_target).copied() else {
+            continue;
+        };
+        // Enemies can only move in straight lines, so finish walking to the middle of the next
+        // tile before heading off on the new path
+        let pos = transform.translation.truncate() / 32.0;
+        let next_tile = |pos: f32, target: i8| {
+            let target = target as f32;
+            if target > pos {
+                pos.ceil() as i8
+            } else if target < pos {
+                pos.floor() as i8
+            } else {
+                pos.round() as i8
+            }
+        };
+        let next = (next_tile(pos.x, target.0), next_tile(pos.y, target.1));
+        // If a tower went up on the tile it was walking into, turn back to the tile it's on
+        let route = follow_distances(&distances, next)
+            .map(|path| (next, path))
+            .or_else(|| {
+                let current = enemy.current_grid_pos;
+                follow_distances(&distances, current).map(|path| (current, path))
+            });
+        if let Some((start, mut path)) = route {
+            path.insert(0, start);
+            enemy.path = path;
+            enemy.path_target = 0;
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct HealthBar(pub f32);
 
@@ -164,12 +247,12 @@ pub fn enemy_movement(
         let mut distance_to_travel = enemy.move_speed * time.delta_seconds();
         for (speed_up_point, transform2) in speed_up_points.iter() {
             let distance = (transform2.translation - transform.translation).length();
-            if distance <= 48.0 {
+            if distance <= SPEED_UP_RADIUS {
                 distance_to_travel += (speed_up_point.0 / 100.0) * distance_to_travel;
             }
         }
         let mut current_pos = transform.translation.truncate();
-        let next_pos = map.path[enemy.path_target];
+        let next_pos = enemy.path[enemy.path_target];
         let next_pos = Map::grid_to_world_pos((next_pos.0 as f32, next_pos.1 as f32));
         let distance_to_next_pos =
             (next_pos.x - current_pos.x).abs() + (next_pos.y - current_pos.y).abs();
@@ -180,7 +263,7 @@ pub fn enemy_movement(
             transform.translation.x = current_pos.x;
             transform.translation.y = current_pos.y;
             enemy.path_target += 1;
-            if enemy.path_target >= map.path.len() {
+            if enemy.path_target >= enemy.path.len() {
                 // Enemy has reached the end
                 if game_manager.lives > 0 {
                     game_manager.lives -= 1;
@@ -207,55 +290,86 @@ pub fn enemy_movement(
     }
 }
 
+/// Heal enemies that regenerate, and recharge shields that haven't been hit for a while.
+pub fn regenerate_enemies(mut enemies: Query<&mut Enemy>, time: Res<Time>) {
+    let delta = time.delta_seconds();
+    for mut enemy in enemies.iter_mut() {
+        enemy.regenerate(delta);
+    }
+}
+
 pub fn update_healthbar(
     mut commands: Commands,
     mut enemies: Query<(Entity, &mut Enemy)>,
     mut healthbars: Query<&mut HealthBar>,
 ) {
     for (entity, mut enemy) in enemies.iter_mut() {
-        if enemy.current_health < enemy.max_health && enemy.current_health > 0.0 {
-            if let Some(hb_entity) = enemy.healthbar {
-                if let Ok(mut healthbar) = healthbars.get_mut(hb_entity) {
-                    healthbar.0 = enemy.current_health / enemy.max_health;
+        if enemy.current_health <= 0.0 {
+            continue;
+        }
+        let health = enemy.current_health / enemy.max_health;
+        let shield = if enemy.max_shield > 0.0 {
+            Some(enemy.current_shield / enemy.max_shield)
+        } else {
+            None
+        };
+        if let Some(hb_entity) = enemy.healthbar {
+            // Enemies can heal, so keep updating the bars once they're shown
+            for (bar, fraction) in [(Some(hb_entity), Some(health)), (enemy.shieldbar, shield)] {
+                if let (Some(bar), Some(fraction)) = (bar, fraction) {
+                    if let Ok(mut healthbar) = healthbars.get_mut(bar) {
+                        if healthbar.0 != fraction {
+                            healthbar.0 = fraction;
+                        }
+                    }
                 }
-            } else {
-                let mut hb_entity = None;
-                commands.entity(entity).with_children(|parent| {
-                    parent
-                        .spawn(SpriteBundle {
-                            sprite: Sprite {
-                                custom_size: Some(Vec2::new(22.0, 4.0)),
-                                color: Color::srgb(0.0, 0.0, 0.0),
-                                ..Default::default()
-                            },
-                            transform: Transform::from_translation(Vec3::new(0.0, -16.0, 6.0)),
-                            ..Default::default()
-                        })
-                        .with_children(|parent| {
-                            hb_entity = Some(
-                                parent
-                                    .spawn(SpriteBundle {
-                                        sprite: Sprite {
-                                            custom_size: Some(Vec2::new(20.0, 2.0)),
-                                            color: GREEN,
-                                            ..Default::default()
-                                        },
-                                        transform: Transform::from_translation(Vec3::new(
-                                            0.0, 0.0, 1.0,
-                                        )),
-                                        ..Default::default()
-                                    })
-                                    .insert(HealthBar(enemy.current_health / enemy.max_health))
-                                    .id(),
-                            );
-                        });
-                });
-                enemy.healthbar = hb_entity;
             }
+        } else if health < 1.0 || shield.is_some_and(|shield| shield < 1.0) {
+            let (mut hb_entity, mut sb_entity) = (None, None);
+            commands.entity(entity).with_children(|parent| {
+                hb_entity = Some(spawn_bar(parent, -16.0, GREEN, health));
+                if let Some(shield) = shield {
+                    sb_entity = Some(spawn_bar(parent, -20.0, BLUE, shield));
+                }
+            });
+            enemy.healthbar = hb_entity;
+            enemy.shieldbar = sb_entity;
         }
     }
 }
 
+/// Spawn a bar below an enemy, returning the part that's scaled to show how full it is.
+fn spawn_bar(parent: &mut ChildBuilder, y: f32, color: Color, fraction: f32) -> Entity {
+    let mut bar = None;
+    parent
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(22.0, 4.0)),
+                color: Color::srgb(0.0, 0.0, 0.0),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(Vec3::new(0.0, y, 6.0)),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            bar = Some(
+                parent
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            custom_size: Some(Vec2::new(20.0, 2.0)),
+                            color,
+                            ..Default::default()
+                        },
+                        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
+                        ..Default::default()
+                    })
+                    .insert(HealthBar(fraction))
+                    .id(),
+            );
+        });
+    bar.unwrap()
+}
+
 pub fn scale_healthbar(mut healthbars: Query<(&HealthBar, &mut Transform), Changed<HealthBar>>) {
     for (healthbar, mut transform) in healthbars.iter_mut() {
         transform.scale.x = healthbar.0;
@@ -282,3 +396,95 @@ pub fn check_killed(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_enemy(variant: EnemyVariant) -> Enemy {
+        Enemy::new(variant, (0, 0), Vec::new(), 1.0, 1.0)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn armour_takes_a_flat_amount_off_each_hit() {
+        let mut enemy = new_enemy(EnemyVariant::Strong);
+        enemy.current_shield = 0.0;
+        enemy.take_damage(1.0);
+        assert_close(enemy.current_health, 20.0 - 0.9);
+
+        // Unarmoured enemies take the full hit
+        let mut enemy = new_enemy(EnemyVariant::Normal);
+        enemy.take_damage(1.0);
+        assert_close(enemy.current_health, 4.0);
+    }
+
+    #[test]
+    fn weak_hits_still_get_through_armour() {
+        let mut enemy = new_enemy(EnemyVariant::UltraBoss);
+        enemy.current_shield = 0.0;
+        enemy.take_damage(0.1);
+        assert_close(enemy.current_health, 250.0 - 0.1 * MIN_DAMAGE);
+    }
+
+    #[test]
+    fn laser_chips_away_at_a_strong_enemy() {
+        // A common laser against the Strong enemy's 0.1 armour, which used to do nothing
+        let mut enemy = new_enemy(EnemyVariant::Strong);
+        enemy.take_damage(0.16);
+        assert_close(enemy.current_shield, 5.0 - 0.06);
+        assert_close(enemy.current_health, 20.0);
+    }
+
+    #[test]
+    fn shield_absorbs_damage_before_health() {
+        let mut enemy = new_enemy(EnemyVariant::Boss);
+        assert_close(enemy.current_shield, 25.0);
+        enemy.take_damage(20.15);
+        assert_close(enemy.current_shield, 5.0);
+        assert_close(enemy.current_health, 100.0);
+
+        // Whatever the shield can't take goes through to health
+        enemy.take_damage(10.15);
+        assert_close(enemy.current_shield, 0.0);
+        assert_close(enemy.current_health, 95.0);
+        assert_eq!(enemy.since_hit, 0.0);
+    }
+
+    #[test]
+    fn regeneration_stops_at_max_health() {
+        let mut enemy = new_enemy(EnemyVariant::StrongFast);
+        enemy.take_damage(0.45);
+        assert_close(enemy.current_health, 19.6);
+        enemy.regenerate(0.5);
+        assert_close(enemy.current_health, 19.8);
+        enemy.regenerate(10.0);
+        assert_eq!(enemy.current_health, enemy.max_health);
+    }
+
+    #[test]
+    fn shield_recharges_after_a_delay_up_to_its_max() {
+        let mut enemy = new_enemy(EnemyVariant::Strong);
+        enemy.take_damage(4.1);
+        assert_close(enemy.current_shield, 1.0);
+        enemy.regenerate(SHIELD_RECHARGE_DELAY - 0.5);
+        assert_close(enemy.current_shield, 1.0);
+        enemy.regenerate(1.0);
+        assert_close(enemy.current_shield, 2.0);
+        enemy.regenerate(100.0);
+        assert_eq!(enemy.current_shield, enemy.max_shield);
+    }
+
+    #[test]
+    fn dead_enemies_do_not_regenerate() {
+        let mut enemy = new_enemy(EnemyVariant::Boss);
+        enemy.current_shield = 0.0;
+        enemy.take_damage(200.0);
+        enemy.regenerate(100.0);
+        assert!(enemy.current_health <= 0.0);
+        assert_eq!(enemy.current_shield, 0.0);
+    }
+}
@@ -1,337 +1,284 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{prelude::*, render::view::NoFrustumCulling};
 use bevy_kira_audio::{AudioChannel, AudioControl};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    actions::{self, PlayerAction},
     audio::{DrumsChannel, VolumeSettings},
-    enemies::{Enemy, EnemyVariant},
+    enemies::{self, Enemy},
     grid::Map,
+    replay::Recording,
+    rng::GameRng,
     state::{loading::GameAssets, results::Scores, State},
-    tower::{debuffs::Debuff, Tower, TowerType},
+    tower::{
+        self,
+        debuffs::{self, Debuff},
+        Tower, TowerPlaced, TowerRemoved, TowerType,
+    },
     ui::{tower_options::present_tower_options, UiState, UiStateResource},
+    waves::{Wave, WaveGenerator, WaveList},
 };
 
-pub struct WaveSegment {
-    pub enemy_type: EnemyVariant,
-    pub count: usize,
-    pub spawn_rate: f32,
+/// How long the countdown to automatically start the next wave lasts, in seconds.
+pub const AUTO_START_SECONDS: f32 = 20.0;
+/// The fraction of the points left in the waves already in play that is awarded for calling the
+/// next wave early.
+pub const EARLY_CALL_BONUS: f32 = 0.5;
+/// How many times a second the game is simulated. Gameplay runs on a fixed timestep so that a
+/// run can be replayed exactly.
+pub const TICKS_PER_SECOND: f64 = 60.0;
+/// How many upgrade points clearing a wave earns. Waves that are called early only pay out once
+/// the field is clear.
+pub const UPGRADE_POINTS_PER_WAVE: u32 = 1;
+
+/// The systems that play the game itself, without any of the UI or input. These are also used
+/// by the headless simulation.
+///
+/// The order these systems visit entities in has to be the same with or without rendering, so
+/// entities they query are spawned with `NoFrustumCulling`. Otherwise rendering adds bounding
+/// boxes to them between frames, which moves them around in storage.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct GameplaySet;
+
+/// The kind of run being played.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunMode {
+    #[default]
+    Normal,
+    /// The daily challenge for the given day, counted from the Unix epoch.
+    Daily(u64),
 }
 
-pub struct Wave {
-    pub segments: Vec<WaveSegment>,
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TowerPlaced>()
+            .add_event::<TowerRemoved>()
+            .add_event::<PlayerAction>()
+            .insert_resource(Time::<Fixed>::from_hz(TICKS_PER_SECOND))
+            // Everything runs in a fixed order, so that a run plays out the same way every time
+            // it's replayed with the same seed and actions
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        actions::apply_tower_actions,
+                        actions::apply_upgrade_actions,
+                        actions::apply_sell_actions,
+                        actions::apply_game_actions,
+                    )
+                        .chain(),
+                    start_next_wave,
+                    gameloop,
+                    enemies::enemy_movement,
+                    enemies::update_enemy_grid_pos,
+                    enemies::reroute_enemies,
+                    (tower::handle_tower_placement, tower::handle_tower_removal).chain(),
+                    (debuffs::update_modifiers, tower::update_ranges).chain(),
+                    debuffs::handle_overheat,
+                    tower::charge_shot::shoot,
+                    tower::sniper::shoot,
+                    tower::laser::shoot,
+                    tower::handle_projectiles,
+                    tower::missile::spawn_missile,
+                    tower::missile::handle_missile,
+                    tower::jammer::rotate_dish,
+                    enemies::check_killed,
+                    (
+                        enemies::regenerate_enemies,
+                        enemies::update_healthbar,
+                        enemies::scale_healthbar,
+                    )
+                        .chain(),
+                    count_tick,
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
 }
 
 pub enum WaveState {
     /// Waiting for the next wave to start.
     Waiting,
-    /// The wave is currently spawning enemies. Contains the number of enemies spawned so far.
-    Spawning(u16),
-    /// The wave is finished, but there are still enemies alive.
+    /// At least one wave is still spawning enemies.
+    Spawning,
+    /// Every wave is finished spawning, but there are still enemies alive.
     Finished,
 }
 
+/// A wave that has been started. Waves can overlap when the next wave is called early, so each
+/// one spawns its enemies independently.
+pub struct ActiveWave {
+    pub number: usize,
+    pub wave: Wave,
+    /// The number of enemies spawned so far.
+    pub spawned: usize,
+    pub spawn_timer: Timer,
+}
+impl ActiveWave {
+    pub fn new(number: usize, wave: Wave) -> Self {
+        // Wait for the first segment's delay before spawning anything
+        let delay = wave.segments.first().map_or(0.0, |segment| segment.delay);
+        Self {
+            number,
+            wave,
+            spawned: 0,
+            spawn_timer: Timer::from_seconds(delay, TimerMode::Once),
+        }
+    }
+
+    pub fn is_spawning(&self) -> bool {
+        self.spawned < self.wave.segments.iter().map(|s| s.count).sum()
+    }
+
+    /// The total points of the enemies that haven't spawned yet.
+    pub fn remaining_points(&self) -> u32 {
+        let mut count = 0;
+        let mut points = 0;
+        for segment in self.wave.segments.iter() {
+            let remaining = (count + segment.count).saturating_sub(self.spawned.max(count));
+            points += remaining as u32 * segment.enemy_type.points();
+            count += segment.count;
+        }
+        points
+    }
+}
+
 #[derive(Resource)]
 pub struct GameManager {
+    /// The number of ticks played so far.
+    pub tick: u64,
+    /// The most recently started wave, or the next one while waiting.
     pub current_wave: usize,
     pub waves: Vec<Wave>,
+    /// The waves file `waves` was loaded from, so it can be reloaded when the file changes.
+    pub wave_source: Handle<WaveList>,
+    pub generator: WaveGenerator,
+    /// The most recently generated endless mode wave, along with its wave number.
+    pub endless_wave: Option<(usize, Wave)>,
     pub wave_state: WaveState,
-    pub spawn_timer: Timer,
+    pub active_waves: Vec<ActiveWave>,
+    /// Counts down to starting the next wave automatically. `None` if auto-start is off.
+    pub countdown: Option<Timer>,
     pub lives: u16,
     pub score: u32,
+    /// Spent on upgrading towers between waves.
+    pub upgrade_points: u32,
+    /// Waves called early that haven't been cleared yet.
+    pub called_early: u32,
+    /// Tower offers still to be shown once the current one is picked from, for waves that were
+    /// called early.
+    pub owed_offers: u32,
     pub health_multiplier: f32,
     pub speed_multiplier: f32,
 }
 impl GameManager {
-    pub fn new() -> Self {
+    pub fn new(wave_source: Handle<WaveList>, waves: Vec<Wave>, generator: WaveGenerator) -> Self {
         Self {
+            tick: 0,
             current_wave: 0,
-            waves: vec![
-                Wave { segments: vec![] },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Weak,
-                        count: 5,
-                        spawn_rate: 0.5,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Weak,
-                            count: 5,
-                            spawn_rate: 1.0,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 3,
-                            spawn_rate: 0.5,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Normal,
-                        count: 10,
-                        spawn_rate: 0.75,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 8,
-                            spawn_rate: 1.0,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Fast,
-                            count: 3,
-                            spawn_rate: 0.5,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 10,
-                            spawn_rate: 1.0,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Fast,
-                            count: 5,
-                            spawn_rate: 0.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 5,
-                            spawn_rate: 1.5,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Fast,
-                        count: 15,
-                        spawn_rate: 0.8,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 15,
-                            spawn_rate: 1.2,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Strong,
-                            count: 1,
-                            spawn_rate: 0.7,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 5,
-                            spawn_rate: 0.7,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Strong,
-                            count: 5,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Strong,
-                        count: 8,
-                        spawn_rate: 0.4,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Strong,
-                            count: 1,
-                            spawn_rate: 1.0,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Fast,
-                            count: 5,
-                            spawn_rate: 1.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Strong,
-                            count: 1,
-                            spawn_rate: 1.0,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Fast,
-                            count: 5,
-                            spawn_rate: 1.5,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Boss,
-                        count: 1,
-                        spawn_rate: 0.7,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 5,
-                            spawn_rate: 0.7,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Strong,
-                            count: 5,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Normal,
-                            count: 10,
-                            spawn_rate: 1.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Boss,
-                            count: 3,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Fast,
-                            count: 20,
-                            spawn_rate: 2.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::StrongFast,
-                            count: 1,
-                            spawn_rate: 0.5,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::StrongFast,
-                            count: 7,
-                            spawn_rate: 1.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Boss,
-                            count: 3,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::StrongFast,
-                            count: 12,
-                            spawn_rate: 1.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Boss,
-                            count: 5,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Boss,
-                        count: 10,
-                        spawn_rate: 0.4,
-                    }],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::StrongFast,
-                        count: 15,
-                        spawn_rate: 2.5,
-                    }],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Normal,
-                        count: 10,
-                        spawn_rate: 1.0,
-                    }],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::UltraBoss,
-                        count: 1,
-                        spawn_rate: 1.5,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Boss,
-                            count: 5,
-                            spawn_rate: 0.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::StrongFast,
-                            count: 10,
-                            spawn_rate: 1.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::Strong,
-                        count: 20,
-                        spawn_rate: 2.5,
-                    }],
-                },
-                Wave {
-                    segments: vec![
-                        WaveSegment {
-                            enemy_type: EnemyVariant::Boss,
-                            count: 5,
-                            spawn_rate: 0.5,
-                        },
-                        WaveSegment {
-                            enemy_type: EnemyVariant::UltraBoss,
-                            count: 2,
-                            spawn_rate: 0.2,
-                        },
-                    ],
-                },
-                Wave {
-                    segments: vec![WaveSegment {
-                        enemy_type: EnemyVariant::UltraBoss,
-                        count: 5,
-                        spawn_rate: 0.3,
-                    }],
-                },
-            ],
-
+            waves,
+            wave_source,
+            generator,
+            endless_wave: None,
             wave_state: WaveState::Waiting,
-            spawn_timer: Timer::from_seconds(0.1, TimerMode::Once),
+            active_waves: Vec::new(),
+            countdown: None,
             lives: 15,
             score: 0,
+            upgrade_points: 0,
+            called_early: 0,
+            owed_offers: 0,
             health_multiplier: 1.0,
             speed_multiplier: 1.0,
         }
     }
+
+    /// The waves being played, as they'd be stored in a waves file.
+    pub fn wave_list(&self) -> WaveList {
+        WaveList {
+            waves: self.waves.clone(),
+            endless: self.generator.settings.clone(),
+        }
+    }
+
+    /// Swap in a new list of waves. The run keeps its seed unless the list sets one.
+    pub fn load_waves(&mut self, list: &WaveList) {
+        self.waves = list.waves.clone();
+        self.generator = WaveGenerator {
+            settings: list.endless.clone(),
+            seed: list.endless.seed.unwrap_or(self.generator.seed),
+        };
+    }
+
+    /// The wave with the given number. Once we've run out of waves, this is the generated wave.
+    pub fn wave(&self, number: usize) -> Option<&Wave> {
+        if number < self.waves.len() {
+            self.waves.get(number)
+        } else {
+            match &self.endless_wave {
+                Some((endless_number, wave)) if *endless_number == number => Some(wave),
+                _ => None,
+            }
+        }
+    }
+
+    /// Make sure a wave is ready to be played, generating it if it's an endless mode wave.
+    pub fn prepare_wave(&mut self, number: usize, spawns: usize) {
+        if number >= self.waves.len() && self.wave(number).is_none() {
+            let wave = self.generator.generate(number - self.waves.len(), spawns);
+            self.endless_wave = Some((number, wave));
+        }
+    }
+
+    /// The wave shown in the preview: the next wave to start, or the one after the current wave
+    /// if it has already started.
+    pub fn upcoming_wave(&self) -> usize {
+        match self.wave_state {
+            WaveState::Waiting => self.current_wave,
+            WaveState::Spawning | WaveState::Finished => self.current_wave + 1,
+        }
+    }
+
+    /// Start spawning the current wave alongside any waves already in play.
+    pub fn start_wave(&mut self, spawns: usize) {
+        self.prepare_wave(self.current_wave, spawns);
+        let wave = self.wave(self.current_wave).cloned().unwrap_or_default();
+        self.active_waves
+            .push(ActiveWave::new(self.current_wave, wave));
+        // Generate the wave after this one now so it can be previewed
+        self.prepare_wave(self.current_wave + 1, spawns);
+        self.wave_state = WaveState::Spawning;
+        if let Some(countdown) = &mut self.countdown {
+            countdown.reset();
+        }
+    }
+}
+
+/// The towers offered after clearing a wave. The first offer of a run is always the same.
+pub fn tower_offer(first: bool, rng: &mut GameRng) -> Vec<Tower> {
+    if first {
+        return vec![
+            Tower::new(0.5, 1.8, TowerType::ChargeShot, Debuff::LaserIncompatible),
+            Tower::new(
+                0.17,
+                4.0,
+                TowerType::Laser,
+                Debuff::ReduceColumnDamage(10.0),
+            ),
+            Tower::new(
+                3.0,
+                0.3,
+                TowerType::Sniper,
+                Debuff::ReduceNeighbourRate(15.0),
+            ),
+        ];
+    }
+    (0..3).map(|_| Tower::new_random(rng.offers())).collect()
 }
 
 pub fn gameloop(
@@ -342,38 +289,43 @@ pub fn gameloop(
     time: Res<Time>,
     mut ui_state: ResMut<UiStateResource>,
     game_assets: Res<GameAssets>,
+    mut rng: ResMut<GameRng>,
     drums_channel: Res<AudioChannel<DrumsChannel>>,
 ) {
     match game_manager.wave_state {
-        WaveState::Spawning(num) => {
-            game_manager.spawn_timer.tick(time.delta());
-            if game_manager.spawn_timer.finished() {
-                let mut finished = true;
-                let wave_index = if game_manager.current_wave < game_manager.waves.len() {
-                    game_manager.current_wave
-                } else {
-                    // if we've run out of waves, alternate between the last 4
-                    (game_manager.current_wave % 4) + game_manager.waves.len() - 4
-                };
-                let wave = &game_manager.waves[wave_index];
+        WaveState::Spawning => {
+            let game_manager = &mut *game_manager;
+            for active in game_manager.active_waves.iter_mut() {
+                active.spawn_timer.tick(time.delta());
+                if !active.spawn_timer.finished() {
+                    continue;
+                }
                 let mut count = 0;
-                for segment in wave.segments.iter() {
-                    if count + segment.count > num.into() {
+                for (i, segment) in active.wave.segments.iter().enumerate() {
+                    if count + segment.count > active.spawned {
+                        let spawn = if segment.spawn < map.spawns.len() {
+                            segment.spawn
+                        } else {
+                            warn!(
+                                "Wave segment uses spawn {} which the map doesn't have",
+                                segment.spawn
+                            );
+                            0
+                        };
+                        let spawn_grid_pos = map.spawns[spawn];
+                        let enemy = segment.enemy(
+                            spawn_grid_pos,
+                            map.spawn_path(spawn, &mut *rng),
+                            game_manager.health_multiplier,
+                            game_manager.speed_multiplier,
+                        );
                         let spawn_pos = Map::grid_to_world_pos((
-                            map.start_pos.0 as f32,
-                            map.start_pos.1 as f32,
+                            spawn_grid_pos.0 as f32,
+                            spawn_grid_pos.1 as f32,
                         ));
                         commands
                             .spawn(SpriteBundle {
-                                texture: match segment.enemy_type {
-                                    EnemyVariant::Weak => game_assets.enemy1.clone(),
-                                    EnemyVariant::Normal => game_assets.enemy2.clone(),
-                                    EnemyVariant::Fast => game_assets.enemy3.clone(),
-                                    EnemyVariant::Strong => game_assets.enemy4.clone(),
-                                    EnemyVariant::Boss => game_assets.enemy5.clone(),
-                                    EnemyVariant::StrongFast => game_assets.enemy6.clone(),
-                                    EnemyVariant::UltraBoss => game_assets.enemy7.clone(),
-                                },
+                                texture: game_assets.enemy_texture(segment.enemy_type),
                                 transform: Transform::from_translation(Vec3::new(
                                     spawn_pos.x,
                                     spawn_pos.y,
@@ -381,60 +333,46 @@ pub fn gameloop(
                                 )),
                                 ..Default::default()
                             })
-                            .insert(Enemy::new(
-                                segment.enemy_type,
-                                map.start_pos,
-                                game_manager.health_multiplier,
-                                game_manager.speed_multiplier,
-                            ));
-                        let cooldown = 1.0 / segment.spawn_rate;
-                        game_manager
+                            .insert(NoFrustumCulling)
+                            .insert(enemy);
+                        let mut cooldown = 1.0 / segment.spawn_rate;
+                        if count + segment.count == active.spawned + 1 {
+                            // This was the last enemy in the segment, so wait for the next one
+                            if let Some(next_segment) = active.wave.segments.get(i + 1) {
+                                cooldown += next_segment.delay;
+                            }
+                        }
+                        active
                             .spawn_timer
                             .set_duration(Duration::from_secs_f32(cooldown));
-                        game_manager.spawn_timer.reset();
-                        game_manager.wave_state = WaveState::Spawning(num + 1);
-                        finished = false;
+                        active.spawn_timer.reset();
+                        active.spawned += 1;
                         break;
                     } else {
                         count += segment.count;
                     }
                 }
-                if finished {
-                    game_manager.wave_state = WaveState::Finished;
-                }
+            }
+            game_manager.active_waves.retain(ActiveWave::is_spawning);
+            if game_manager.active_waves.is_empty() {
+                game_manager.wave_state = WaveState::Finished;
             }
         }
         WaveState::Finished => {
             if enemies.iter().count() == 0 {
                 drums_channel.set_volume(0.0);
                 game_manager.current_wave += 1;
+                // Waves called early are cleared along with this one, so they earn their upgrade
+                // points and tower offers now
+                let cleared = 1 + game_manager.called_early;
+                game_manager.called_early = 0;
+                game_manager.upgrade_points += UPGRADE_POINTS_PER_WAVE * cleared;
+                game_manager.owed_offers += cleared - 1;
                 game_manager.wave_state = WaveState::Waiting;
-                let mut options = Vec::new();
-                if game_manager.current_wave == 1 {
-                    // Fix the first set of tower options
-                    options.push(Tower::new(
-                        0.5,
-                        1.8,
-                        TowerType::ChargeShot,
-                        Debuff::LaserIncompatible,
-                    ));
-                    options.push(Tower::new(
-                        0.17,
-                        4.0,
-                        TowerType::Laser,
-                        Debuff::ReduceColumnDamage(10.0),
-                    ));
-                    options.push(Tower::new(
-                        3.0,
-                        0.3,
-                        TowerType::Sniper,
-                        Debuff::ReduceNeighbourRate(15.0),
-                    ));
-                } else {
-                    for _ in 0..3 {
-                        options.push(Tower::new_random());
-                    }
-                }
+                let next_wave = game_manager.current_wave;
+                game_manager.prepare_wave(next_wave, map.spawns.len());
+                let first = game_manager.current_wave == cleared as usize;
+                let options = tower_offer(first, &mut rng);
                 present_tower_options(commands, game_assets.font.clone(), &options);
                 ui_state.state = UiState::PickingTower(options);
             }
@@ -443,33 +381,51 @@ pub fn gameloop(
     }
 }
 
+/// Start the first wave straight away, and later waves when the countdown runs out.
 pub fn start_next_wave(
-    input: Res<ButtonInput<KeyCode>>,
     mut game_manager: ResMut<GameManager>,
+    time: Res<Time>,
+    ui_state: Res<UiStateResource>,
     drums_channel: Res<AudioChannel<DrumsChannel>>,
     volume_settings: Res<VolumeSettings>,
+    map: Res<Map>,
 ) {
-    if (input.just_pressed(KeyCode::Space)) || game_manager.current_wave == 0 {
-        if let WaveState::Waiting = game_manager.wave_state {
-            drums_channel.set_volume(volume_settings.music_vol * 1.5);
-            game_manager.wave_state = WaveState::Spawning(0);
-            if game_manager.current_wave >= game_manager.waves.len() {
-                // If we're in endless mode, gradually increase the difficulty
-                game_manager.health_multiplier += 0.1;
-                game_manager.speed_multiplier += 0.1;
+    if let WaveState::Waiting = game_manager.wave_state {
+        // Don't count down while the player is choosing a new tower
+        let mut countdown_finished = false;
+        if !matches!(ui_state.state, UiState::PickingTower(_)) {
+            if let Some(countdown) = &mut game_manager.countdown {
+                countdown.tick(time.delta());
+                countdown_finished = countdown.finished();
             }
         }
+        if game_manager.current_wave == 0 || countdown_finished {
+            drums_channel.set_volume(volume_settings.music_vol * 1.5);
+            game_manager.start_wave(map.spawns.len());
+        }
     }
 }
 
+pub fn count_tick(mut game_manager: ResMut<GameManager>) {
+    game_manager.tick += 1;
+}
+
 pub fn game_over_check(
     game_manager: Res<GameManager>,
+    rng: Res<GameRng>,
+    recording: Option<Res<Recording>>,
+    run_mode: Res<RunMode>,
+    towers: Query<&Tower>,
     mut next_state: ResMut<NextState<State>>,
     mut scores: ResMut<Scores>,
 ) {
     if game_manager.lives == 0 {
         scores.last_score = game_manager.score;
         scores.last_wave = game_manager.current_wave as u32;
+        scores.last_seed = rng.seed();
+        scores.last_mode = *run_mode;
+        scores.last_towers = towers.iter().map(|tower| tower.variant).collect();
+        scores.last_recorded = recording.is_some();
         if game_manager.score > scores.high_score {
             scores.high_score = game_manager.score;
         }
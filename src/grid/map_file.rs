@@ -0,0 +1,190 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::path::{exit_distances, trace_routes, Route, MAX_ROUTES_PER_SPAWN};
+
+/// Tile value for a tile that towers can be built on.
+pub const BUILDABLE_TILE: u8 = 0;
+/// Tile value for a tile that enemies walk along.
+pub const PATH_TILE: u8 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapMode {
+    /// Enemies follow the path tiles from their spawn to the edge of the map.
+    #[default]
+    Path,
+    /// Enemies cross any tile without a tower on it to reach the nearest exit, so towers can be
+    /// used to build a maze. Towers can't be placed where they would block every way out.
+    Maze,
+}
+
+/// A map as it is stored in a `.map.ron` file.
+///
+/// Rows of `tiles` are listed bottom to top, so `tiles[y][x]` is the tile at grid position
+/// `(x, y)`.
+#[derive(Asset, TypePath, Debug, Clone, Serialize, Deserialize)]
+pub struct MapData {
+    pub name: String,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub tiles: Vec<Vec<u8>>,
+    /// Where enemies enter the map. Waves refer to spawns by their index in this list.
+    pub spawns: Vec<(i8, i8)>,
+    #[serde(default)]
+    pub mode: MapMode,
+    /// Where enemies leave the map in maze mode. In path mode the exits are wherever the path
+    /// tiles leave the map.
+    #[serde(default)]
+    pub exits: Vec<(i8, i8)>,
+    /// Path of the waves file to play on this map, relative to the assets folder. Maps without
+    /// one use the default waves.
+    #[serde(default)]
+    pub waves: Option<String>,
+}
+impl MapData {
+    /// Check that the tiles form a rectangular grid and that the path tiles lead from every spawn
+    /// to the edge of the map.
+    pub fn validate(&self) -> Result<(), MapError> {
+        self.validate_shape()?;
+        let (width, height) = (self.tiles[0].len(), self.tiles.len());
+        match self.mode {
+            MapMode::Path => self.trace_routes().map(|_| ()),
+            MapMode::Maze => {
+                if self.spawns.is_empty() {
+                    return Err(MapError::NoSpawns);
+                }
+                if self.exits.is_empty() {
+                    return Err(MapError::NoExits);
+                }
+                let distances =
+                    exit_distances(width as u8, height as u8, &self.spawns, &self.exits, |_| {
+                        false
+                    });
+                match self.spawns.iter().find(|s| !distances.contains_key(s)) {
+                    Some(spawn) => Err(MapError::NoRoute { spawn: *spawn }),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Check that the tiles form a rectangular grid no bigger than positions can hold, made of
+    /// known tiles. Maps in the editor only need this much, as they can be unfinished.
+    pub fn validate_shape(&self) -> Result<(), MapError> {
+        let height = self.tiles.len();
+        let width = self.tiles.first().map_or(0, |row| row.len());
+        if width == 0 || height == 0 {
+            return Err(MapError::Empty);
+        }
+        if width > i8::MAX as usize || height > i8::MAX as usize {
+            return Err(MapError::TooLarge { width, height });
+        }
+        for (y, row) in self.tiles.iter().enumerate() {
+            if row.len() != width {
+                return Err(MapError::RaggedRow {
+                    row: y,
+                    expected: width,
+                    found: row.len(),
+                });
+            }
+            for (x, tile) in row.iter().enumerate() {
+                if *tile != BUILDABLE_TILE && *tile != PATH_TILE {
+                    return Err(MapError::InvalidTile {
+                        pos: (x, y),
+                        value: *tile,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Work out every route enemies can follow from the spawns to the edge of the map.
+    pub fn trace_routes(&self) -> Result<Vec<Route>, MapError> {
+        trace_routes(&self.tiles, &self.spawns)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MapError {
+    #[error("map has no tiles")]
+    Empty,
+    #[error("map is {width}x{height} but can be at most 127x127")]
+    TooLarge { width: usize, height: usize },
+    #[error("row {row} has {found} tiles but the first row has {expected}")]
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("tile at {pos:?} has value {value}, expected 0 (buildable) or 1 (path)")]
+    InvalidTile { pos: (usize, usize), value: u8 },
+    #[error("map has no spawns")]
+    NoSpawns,
+    #[error("map is in maze mode but has no exits")]
+    NoExits,
+    #[error("spawn at {pos:?} is inside the map but not on a path tile")]
+    SpawnNotOnPath { pos: (i8, i8) },
+    #[error("spawn at {spawn:?} is not next to a path tile")]
+    NoEntrance { spawn: (i8, i8) },
+    #[error("path dead-ends at {pos:?} without reaching the edge of the map")]
+    DeadEnd { pos: (i8, i8) },
+    #[error("path ends in the corner at {pos:?}, so it could leave the map in two directions")]
+    AmbiguousExit { pos: (i8, i8) },
+    #[error("no path from the spawn at {spawn:?} reaches an exit")]
+    NoRoute { spawn: (i8, i8) },
+    #[error(
+        "path from the spawn at {spawn:?} branches into more than {MAX_ROUTES_PER_SPAWN} routes"
+    )]
+    TooManyRoutes { spawn: (i8, i8) },
+    #[error("path tile at {pos:?} can't be reached from any spawn")]
+    UnreachablePathTile { pos: (usize, usize) },
+}
+
+#[derive(Debug, Error)]
+pub enum MapLoadError {
+    #[error("could not read map file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse map file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("invalid map: {0}")]
+    Invalid(#[from] MapError),
+}
+
+#[derive(Default)]
+pub struct MapLoader;
+
+impl AssetLoader for MapLoader {
+    type Asset = MapData;
+    type Settings = ();
+    type Error = MapLoadError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<MapData, MapLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let map: MapData = ron::de::from_bytes(&bytes)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::state::loading::GameAssets;
+
+use self::{
+    map_file::{MapData, MapError, MapMode, PATH_TILE},
+    path::{exit_distances, follow_distances, Route},
+};
+
+pub mod map_file;
+pub mod path;
+
+#[derive(Resource, Debug)]
+pub struct Map {
+    pub grid: Vec<Vec<u8>>,
+    pub width: u8,
+    pub height: u8,
+    pub mode: MapMode,
+    pub spawns: Vec<(i8, i8)>,
+    pub exits: Vec<(i8, i8)>,
+    pub placements: HashMap<(i8, i8), Entity>,
+    pub enemies: HashMap<(i8, i8), Vec<Entity>>,
+    pub routes: Vec<Route>,
+}
+impl Map {
+    pub fn from_data(data: &MapData) -> Result<Self, MapError> {
+        data.validate()?;
+        let routes = match data.mode {
+            MapMode::Path => data.trace_routes()?,
+            MapMode::Maze => Vec::new(),
+        };
+        Ok(Self {
+            grid: data.tiles.clone(),
+            width: data.tiles[0].len() as u8,
+            height: data.tiles.len() as u8,
+            mode: data.mode,
+            spawns: data.spawns.clone(),
+            exits: data.exits.clone(),
+            placements: HashMap::new(),
+            enemies: HashMap::new(),
+            routes,
+        })
+    }
+
+    /// Work out the waypoints for a new enemy entering from the given spawn.
+    pub fn spawn_path<R: Rng + ?Sized>(&self, spawn: usize, rng: &mut R) -> Vec<(i8, i8)> {
+        match self.mode {
+            MapMode::Path => self.routes[self.choose_route(spawn, rng)].waypoints.clone(),
+            MapMode::Maze => self.path_to_exit(self.spawns[spawn]).unwrap_or_default(),
+        }
+    }
+
+    /// Pick one of the routes from the given spawn, choosing evenly between the branches each
+    /// time the path forks. Returns an index into `routes`.
+    pub fn choose_route<R: Rng + ?Sized>(&self, spawn: usize, rng: &mut R) -> usize {
+        let mut candidates: Vec<usize> = (0..self.routes.len())
+            .filter(|i| self.routes[*i].spawn == spawn)
+            .collect();
+        let mut step = 0;
+        while candidates.len() > 1 {
+            // Group the remaining routes by where they head next
+            let mut branches: Vec<(i8, i8)> = candidates
+                .iter()
+                .filter_map(|i| self.routes[*i].waypoints.get(step).copied())
+                .collect();
+            branches.sort_unstable();
+            branches.dedup();
+            if let Some(branch) = branches.choose(rng) {
+                candidates.retain(|i| self.routes[*i].waypoints.get(step) == Some(branch));
+            }
+            step += 1;
+        }
+        candidates.first().copied().unwrap_or(0)
+    }
+
+    /// How many steps it takes to reach the nearest exit from every walkable position in maze
+    /// mode, optionally treating one extra tile as if it had a tower on it.
+    pub fn exit_distances(&self, extra_blocked: Option<(i8, i8)>) -> HashMap<(i8, i8), u32> {
+        exit_distances(self.width, self.height, &self.spawns, &self.exits, |pos| {
+            self.placements.contains_key(&pos) || Some(pos) == extra_blocked
+        })
+    }
+
+    /// The shortest way from `from` to an exit in maze mode, avoiding towers.
+    pub fn path_to_exit(&self, from: (i8, i8)) -> Option<Vec<(i8, i8)>> {
+        follow_distances(&self.exit_distances(None), from)
+    }
+
+    /// Whether a tower at `pos` would leave a spawn or an enemy with no way to an exit.
+    pub fn would_block(&self, pos: (i8, i8)) -> bool {
+        if self.mode != MapMode::Maze {
+            return false;
+        }
+        let distances = self.exit_distances(Some(pos));
+        self.spawns
+            .iter()
+            .chain(
+                self.enemies
+                    .iter()
+                    .filter(|(_, entities)| !entities.is_empty())
+                    .map(|(pos, _)| pos),
+            )
+            .any(|pos| !distances.contains_key(pos))
+    }
+
+    pub fn get_grid_pos(pos: Vec2) -> (i8, i8) {
+        let x = pos.x + 16.0;
+        let y = pos.y + 16.0;
+        let x = if x < 0.0 { -1 } else { (x / 32.0) as i8 };
+        let y = if y < 0.0 { -1 } else { (y / 32.0) as i8 };
+        (x, y)
+    }
+
+    pub fn grid_to_world_pos(pos: (f32, f32)) -> Vec2 {
+        let (x, y) = pos;
+        Vec2::new(x * 32.0, y * 32.0)
+    }
+
+    pub fn is_within_bounds(&self, pos: (i8, i8)) -> bool {
+        let (x, y) = pos;
+        if x >= self.width.try_into().unwrap()
+            || y >= self.height.try_into().unwrap()
+            || x < 0
+            || y < 0
+        {
+            return false;
+        }
+        true
+    }
+
+    pub fn is_valid_placement(&self, pos: (i8, i8)) -> bool {
+        let (x, y) = pos;
+        if !self.is_within_bounds(pos)
+            || self.grid[y as usize][x as usize] == PATH_TILE
+            || self.placements.contains_key(&(x, y))
+        {
+            return false;
+        }
+        if self.mode == MapMode::Maze
+            && (self.enemies.get(&pos).is_some_and(|e| !e.is_empty()) || self.would_block(pos))
+        {
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn place_tower(&mut self, pos: (i8, i8), entity: Entity) -> Result<(), ()> {
+        let (x, y) = pos;
+        if self.is_valid_placement(pos) {
+            self.placements.insert((x, y), entity);
+            return Ok(());
+        }
+
+        Err(())
+    }
+}
+
+/// Center the camera on a map of the given size, zooming out for maps bigger than 6x6.
+pub fn fit_camera_to_map(
+    width: u8,
+    height: u8,
+    projection: &mut OrthographicProjection,
+    transform: &mut Transform,
+) {
+    projection.scale = 0.4 * (width.max(height) as f32 / 6.0).max(1.0);
+    transform.translation.x = width as f32 * 16.0 - 20.0;
+    transform.translation.y = height as f32 * 16.0 - 20.0;
+}
+
+/// The map the next game will be played on.
+#[derive(Resource, Debug)]
+pub struct SelectedMap(pub Handle<MapData>);
+
+pub fn load_map(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    selected_map: Res<SelectedMap>,
+    maps: Res<Assets<MapData>>,
+    mut cameras: Query<(&mut OrthographicProjection, &mut Transform)>,
+) {
+    let data = maps
+        .get(&selected_map.0)
+        .expect("Map asset should be loaded before the game starts");
+    let map = Map::from_data(data).expect("Map should have been validated when it was loaded");
+
+    for (mut projection, mut transform) in cameras.iter_mut() {
+        fit_camera_to_map(map.width, map.height, &mut projection, &mut transform);
+    }
+
+    for (y, row) in map.grid.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            if *tile == PATH_TILE {
+                commands.spawn(SpriteBundle {
+                    texture: game_assets.empty_tile.clone(),
+                    transform: Transform::from_translation(Vec3::new(
+                        x as f32 * 32.0,
+                        y as f32 * 32.0,
+                        0.0,
+                    )),
+                    ..Default::default()
+                });
+            } else {
+                commands.spawn(SpriteBundle {
+                    texture: game_assets.buildable_tile.clone(),
+                    transform: Transform::from_translation(Vec3::new(
+                        x as f32 * 32.0,
+                        y as f32 * 32.0,
+                        0.0,
+                    )),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    commands.insert_resource(map);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    #[test]
+    fn choose_route_picks_every_branch_of_a_fork() {
+        // A path from the left that splits to leave through the top and the bottom
+        let mut grid = vec![vec![0; 5]; 5];
+        for row in grid.iter_mut() {
+            row[2] = PATH_TILE;
+        }
+        grid[2][0] = PATH_TILE;
+        grid[2][1] = PATH_TILE;
+        let spawns = vec![(-1, 2)];
+        let map = Map {
+            routes: path::trace_routes(&grid, &spawns).unwrap(),
+            grid,
+            width: 5,
+            height: 5,
+            mode: MapMode::Path,
+            spawns,
+            exits: Vec::new(),
+            placements: HashMap::new(),
+            enemies: HashMap::new(),
+        };
+        assert_eq!(map.routes.len(), 2);
+
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut chosen = [0; 2];
+        for _ in 0..100 {
+            chosen[map.choose_route(0, &mut rng)] += 1;
+        }
+        assert!(chosen.iter().all(|count| *count > 20), "{chosen:?}");
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::map_file::{MapError, PATH_TILE};
+
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// The most routes a single spawn may have. Grids where path tiles loop back on themselves a lot
+/// produce a huge number of routes, which is almost certainly a mistake.
+pub const MAX_ROUTES_PER_SPAWN: usize = 32;
+
+/// A way through the map from one of the spawns to the edge of the map.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// Index into the map's spawns.
+    pub spawn: usize,
+    /// The corners the enemies have to walk between. The last waypoint is the tile just outside
+    /// the map where the route exits.
+    pub waypoints: Vec<(i8, i8)>,
+}
+
+/// Follow the path tiles from every spawn until they leave the map, returning every route that
+/// enemies could take. Where the path forks, a separate route is returned for each branch.
+///
+/// Each spawn may either be a path tile or a position just outside the map next to one. Branches
+/// that lead back to a spawn or back onto themselves are not routes, but branches that end
+/// without reaching the edge of the map are rejected.
+pub fn trace_routes(tiles: &[Vec<u8>], spawns: &[(i8, i8)]) -> Result<Vec<Route>, MapError> {
+    let height = tiles.len() as i32;
+    let width = tiles.first().map_or(0, |row| row.len()) as i32;
+    let in_bounds = |(x, y): (i32, i32)| x >= 0 && y >= 0 && x < width && y < height;
+    let is_path =
+        |pos: (i32, i32)| in_bounds(pos) && tiles[pos.1 as usize][pos.0 as usize] == PATH_TILE;
+    let spawn_positions: Vec<(i32, i32)> =
+        spawns.iter().map(|(x, y)| (*x as i32, *y as i32)).collect();
+
+    if spawns.is_empty() {
+        return Err(MapError::NoSpawns);
+    }
+    let mut routes = Vec::new();
+    let mut visited = HashSet::new();
+    for (index, spawn) in spawns.iter().enumerate() {
+        let start = spawn_positions[index];
+        if in_bounds(start) && !is_path(start) {
+            return Err(MapError::SpawnNotOnPath { pos: *spawn });
+        }
+        let mut spawn_routes = Vec::new();
+        // Each walk is the list of tiles walked so far, along with the same tiles as a set
+        let mut walks = vec![(vec![start], HashSet::from([start]))];
+        while let Some((mut walk, mut walked)) = walks.pop() {
+            loop {
+                let pos = walk[walk.len() - 1];
+                let previous = (walk.len() > 1).then(|| walk[walk.len() - 2]);
+                let direction = previous.map(|p| (pos.0 - p.0, pos.1 - p.1));
+                let next: Vec<(i32, i32)> = DIRECTIONS
+                    .iter()
+                    .map(|(dx, dy)| (pos.0 + dx, pos.1 + dy))
+                    .filter(|p| Some(*p) != previous && is_path(*p))
+                    .collect();
+                if next.is_empty() {
+                    if !in_bounds(pos) {
+                        return Err(MapError::NoEntrance { spawn: *spawn });
+                    }
+                    // The path ends here, so it has to leave the map. Prefer carrying straight
+                    // on, otherwise there must be exactly one way off the edge.
+                    let exits: Vec<(i32, i32)> = DIRECTIONS
+                        .iter()
+                        .copied()
+                        .filter(|(dx, dy)| {
+                            let p = (pos.0 + dx, pos.1 + dy);
+                            Some(p) != previous && !in_bounds(p)
+                        })
+                        .collect();
+                    let exit_direction = match direction {
+                        Some(d) if exits.contains(&d) => d,
+                        _ => match exits[..] {
+                            [] => return Err(MapError::DeadEnd { pos: to_i8(pos) }),
+                            [d] => d,
+                            _ => return Err(MapError::AmbiguousExit { pos: to_i8(pos) }),
+                        },
+                    };
+                    let exit = (pos.0 + exit_direction.0, pos.1 + exit_direction.1);
+                    if !spawn_positions.contains(&exit) {
+                        walk.push(exit);
+                        visited.extend(walked);
+                        spawn_routes.push(Route {
+                            spawn: index,
+                            waypoints: to_waypoints(&walk),
+                        });
+                    }
+                    break;
+                }
+                // Queue up every other branch, then carry on along the first one
+                for branch in next[1..].iter() {
+                    if !walked.contains(branch) && !spawn_positions.contains(branch) {
+                        let mut branch_walk = walk.clone();
+                        branch_walk.push(*branch);
+                        let mut branch_walked = walked.clone();
+                        branch_walked.insert(*branch);
+                        walks.push((branch_walk, branch_walked));
+                    }
+                }
+                if walked.contains(&next[0]) || spawn_positions.contains(&next[0]) {
+                    break;
+                }
+                walk.push(next[0]);
+                walked.insert(next[0]);
+            }
+            if spawn_routes.len() + walks.len() > MAX_ROUTES_PER_SPAWN {
+                return Err(MapError::TooManyRoutes { spawn: *spawn });
+            }
+        }
+        if spawn_routes.is_empty() {
+            return Err(MapError::NoRoute { spawn: *spawn });
+        }
+        routes.append(&mut spawn_routes);
+    }
+
+    // Any path tile that no route walks over would look like part of the path without being used
+    for (y, row) in tiles.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            if *tile == PATH_TILE && !visited.contains(&(x as i32, y as i32)) {
+                return Err(MapError::UnreachablePathTile { pos: (x, y) });
+            }
+        }
+    }
+    Ok(routes)
+}
+
+/// Work out how many steps it takes to reach the nearest exit from every position enemies can
+/// walk on, for maps in maze mode. Positions outside the map can only be walked on if they are a
+/// spawn or an exit.
+pub fn exit_distances(
+    width: u8,
+    height: u8,
+    spawns: &[(i8, i8)],
+    exits: &[(i8, i8)],
+    is_blocked: impl Fn((i8, i8)) -> bool,
+) -> HashMap<(i8, i8), u32> {
+    let walkable = |pos: (i8, i8)| {
+        let in_bounds = pos.0 >= 0 && pos.1 >= 0 && (pos.0 as u8) < width && (pos.1 as u8) < height;
+        (in_bounds && !is_blocked(pos)) || spawns.contains(&pos) || exits.contains(&pos)
+    };
+    let mut distances = HashMap::new();
+    let mut queue = VecDeque::new();
+    for exit in exits {
+        distances.insert(*exit, 0);
+        queue.push_back(*exit);
+    }
+    while let Some(pos) = queue.pop_front() {
+        let distance = distances[&pos];
+        for direction in DIRECTIONS {
+            if let Some(next) = step(pos, direction) {
+                if walkable(next) && !distances.contains_key(&next) {
+                    distances.insert(next, distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    distances
+}
+
+/// Walk from `from` to the nearest exit using distances from [`exit_distances`], returning the
+/// waypoints along the way. Returns `None` if there is no way out from `from`.
+pub fn follow_distances(
+    distances: &HashMap<(i8, i8), u32>,
+    from: (i8, i8),
+) -> Option<Vec<(i8, i8)>> {
+    let mut pos = from;
+    let mut distance = *distances.get(&from)?;
+    let mut direction = None;
+    let mut walk = vec![(from.0 as i32, from.1 as i32)];
+    while distance > 0 {
+        // Prefer carrying straight on so enemies don't zig-zag across open ground
+        let (next_direction, next) = DIRECTIONS
+            .iter()
+            .filter_map(|d| step(pos, *d).map(|p| (*d, p)))
+            .filter(|(_, p)| distances.get(p) == Some(&(distance - 1)))
+            .min_by_key(|(d, _)| Some(*d) != direction)?;
+        walk.push((next.0 as i32, next.1 as i32));
+        direction = Some(next_direction);
+        distance -= 1;
+        pos = next;
+    }
+    Some(to_waypoints(&walk))
+}
+
+fn step(pos: (i8, i8), direction: (i32, i32)) -> Option<(i8, i8)> {
+    Some((
+        pos.0.checked_add(direction.0 as i8)?,
+        pos.1.checked_add(direction.1 as i8)?,
+    ))
+}
+
+/// Reduce a walk over neighbouring tiles to the corners along it, plus the final tile.
+fn to_waypoints(walk: &[(i32, i32)]) -> Vec<(i8, i8)> {
+    let mut waypoints = Vec::new();
+    for window in walk.windows(3) {
+        let incoming = (window[1].0 - window[0].0, window[1].1 - window[0].1);
+        let outgoing = (window[2].0 - window[1].0, window[2].1 - window[1].1);
+        if incoming != outgoing {
+            waypoints.push(to_i8(window[1]));
+        }
+    }
+    waypoints.push(to_i8(walk[walk.len() - 1]));
+    waypoints
+}
+
+fn to_i8(pos: (i32, i32)) -> (i8, i8) {
+    (pos.0 as i8, pos.1 as i8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::grid::map_file::MapData;
+
+    use super::*;
+
+    /// Turn lines of `#` (path) and `.` (buildable), top to bottom as they'd be drawn, into tiles
+    /// listed bottom to top.
+    fn grid(rows: &str) -> Vec<Vec<u8>> {
+        rows.lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .rev()
+            .map(|row| row.chars().map(|c| (c == '#') as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn default_map_waypoints_match_the_old_hand_written_path() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/maps/default.map.ron");
+        let map: MapData = ron::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let routes = map.trace_routes().unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(
+            routes[0].waypoints,
+            [(1, 4), (1, 1), (3, 1), (3, 3), (6, 3)]
+        );
+    }
+
+    #[test]
+    fn path_that_stops_inside_the_map_is_a_dead_end() {
+        let tiles = grid(
+            "
+            ...
+            ##.
+            ...
+            ",
+        );
+        assert!(matches!(
+            trace_routes(&tiles, &[(-1, 1)]),
+            Err(MapError::DeadEnd { pos: (1, 1) })
+        ));
+    }
+
+    #[test]
+    fn path_ending_in_a_corner_has_an_ambiguous_exit() {
+        let tiles = grid(
+            "
+            ...
+            ...
+            #..
+            ",
+        );
+        assert!(matches!(
+            trace_routes(&tiles, &[(0, 0)]),
+            Err(MapError::AmbiguousExit { pos: (0, 0) })
+        ));
+    }
+
+    #[test]
+    fn stray_path_tile_is_unreachable() {
+        let tiles = grid(
+            "
+            .#..
+            ....
+            ####
+            ....
+            ",
+        );
+        assert!(matches!(
+            trace_routes(&tiles, &[(-1, 1)]),
+            Err(MapError::UnreachablePathTile { pos: (1, 3) })
+        ));
+    }
+
+    #[test]
+    fn spawn_inside_the_map_has_to_be_on_the_path() {
+        let tiles = grid(
+            "
+            ...
+            ###
+            ...
+            ",
+        );
+        assert!(matches!(
+            trace_routes(&tiles, &[(1, 0)]),
+            Err(MapError::SpawnNotOnPath { pos: (1, 0) })
+        ));
+        assert!(trace_routes(&tiles, &[(-1, 1)]).is_ok());
+    }
+
+    #[test]
+    fn fork_gives_a_route_for_each_branch() {
+        let tiles = grid(
+            "
+            ..#..
+            ..#..
+            ###..
+            ..#..
+            ..#..
+            ",
+        );
+        let routes = trace_routes(&tiles, &[(-1, 2)]).unwrap();
+        let mut waypoints: Vec<_> = routes.iter().map(|route| route.waypoints.clone()).collect();
+        waypoints.sort();
+        assert_eq!(waypoints, [vec![(2, 2), (2, -1)], vec![(2, 2), (2, 5)]]);
+        assert!(routes.iter().all(|route| route.spawn == 0));
+    }
+
+    #[test]
+    fn each_spawn_gets_its_own_routes() {
+        let tiles = grid(
+            "
+            ..#..
+            ..###
+            ..#..
+            ",
+        );
+        // Carrying straight on from either spawn leads into the other one, which isn't a route
+        let routes = trace_routes(&tiles, &[(2, 3), (2, -1)]).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].spawn, 0);
+        assert_eq!(routes[0].waypoints, [(2, 1), (5, 1)]);
+        assert_eq!(routes[1].spawn, 1);
+        assert_eq!(routes[1].waypoints, [(2, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn chain_of_forks_has_too_many_routes() {
+        // Six forks one after another, each of which doubles the number of routes
+        let tiles = grid(
+            "
+            .###.###.###.###.###.###.
+            ##.###.###.###.###.###.##
+            .###.###.###.###.###.###.
+            ",
+        );
+        assert!(matches!(
+            trace_routes(&tiles, &[(-1, 1)]),
+            Err(MapError::TooManyRoutes { spawn: (-1, 1) })
+        ));
+    }
+}
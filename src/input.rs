@@ -1,32 +1,20 @@
 use bevy::prelude::*;
 
 use crate::{
+    actions::PlayerAction,
     grid::Map,
-    state::loading::GameAssets,
-    tower::{
-        charge_shot::spawn_charge_shot,
-        jammer::spawn_jammer,
-        laser::{spawn_laser, Direction},
-        missile::spawn_silo,
-        sniper::spawn_sniper,
-        RangeIndicator, Tower, TowerPlaced, TowerType,
-    },
-    ui::{inventory::Inventory, UiData, UiState, UiStateResource},
+    tower::{RangeIndicator, Tower},
+    ui::{UiData, UiState, UiStateResource},
 };
 
 pub fn grid_click_handler(
-    commands: Commands,
-    map: ResMut<Map>,
+    map: Res<Map>,
     mut ui_data: ResMut<UiData>,
     mut ui_state: ResMut<UiStateResource>,
-    mut inventory: ResMut<Inventory>,
     mouse_input: Res<ButtonInput<MouseButton>>,
     windows: Query<&Window>,
     camera: Query<(&Camera, &GlobalTransform)>,
-    game_assets: Res<GameAssets>,
-    event_writer: EventWriter<TowerPlaced>,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<ColorMaterial>>,
+    mut actions: EventWriter<PlayerAction>,
 ) {
     match ui_state.state {
         UiState::PlacingTower(i) => {
@@ -40,66 +28,10 @@ pub fn grid_click_handler(
                     let world_position = world_position.origin.truncate();
                     let grid_pos = Map::get_grid_pos(world_position);
                     if map.is_valid_placement(grid_pos) {
-                        let tower = inventory.towers.remove(i);
-                        match tower.variant {
-                            TowerType::ChargeShot => {
-                                spawn_charge_shot(
-                                    tower,
-                                    commands,
-                                    grid_pos,
-                                    game_assets,
-                                    event_writer,
-                                    meshes,
-                                    materials,
-                                    map,
-                                );
-                            }
-                            TowerType::Laser => {
-                                spawn_laser(
-                                    tower,
-                                    commands,
-                                    grid_pos,
-                                    Direction::Down,
-                                    game_assets,
-                                    event_writer,
-                                    meshes,
-                                    materials,
-                                    map,
-                                );
-                            }
-                            TowerType::Sniper => {
-                                spawn_sniper(
-                                    tower,
-                                    commands,
-                                    grid_pos,
-                                    game_assets,
-                                    event_writer,
-                                    meshes,
-                                    materials,
-                                    map,
-                                );
-                            }
-                            TowerType::Jammer => {
-                                spawn_jammer(
-                                    tower,
-                                    commands,
-                                    grid_pos,
-                                    game_assets,
-                                    event_writer,
-                                    meshes,
-                                    materials,
-                                    map,
-                                );
-                            }
-                            TowerType::Missile => spawn_silo(
-                                tower,
-                                commands,
-                                grid_pos,
-                                game_assets,
-                                event_writer,
-                                map,
-                            ),
-                        }
+                        actions.send(PlayerAction::PlaceTower {
+                            index: i,
+                            pos: grid_pos,
+                        });
                         ui_state.state = UiState::Normal;
                     }
                 }
@@ -130,6 +62,15 @@ pub fn grid_click_handler(
     }
 }
 
+pub fn start_wave_key(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        actions.send(PlayerAction::StartWave);
+    }
+}
+
 #[derive(Default, Resource)]
 pub struct HoverPosition(pub Option<(i8, i8)>);
 
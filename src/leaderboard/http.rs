@@ -0,0 +1,374 @@
+//! A leaderboard on a score server of our own. The server needs to answer three requests, all
+//! with JSON bodies:
+//!
+//! - `POST /players` creates a player, and responds with `{"name": "..."}`.
+//! - `POST /scores` stores a [`ServerScore`], along with the run's replay so the server can check
+//!   it with `verify`.
+//! - `GET /scores` responds with every stored [`ServerScore`], in any order. The replays can be
+//!   left out.
+
+use std::{
+    cmp::Reverse,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{prelude::*, tasks::IoTaskPool};
+use serde::{Deserialize, Serialize};
+
+use crate::{replay::Replay, tower::TowerType};
+
+use super::{score_category, LeaderboardBackend, LeaderboardEntry, RunScore};
+
+/// A score as the server stores it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerScore {
+    pub player: Option<String>,
+    pub score: u32,
+    pub wave: u32,
+    pub seed: u64,
+    /// Which leaderboard the score is on. `None` is the leaderboard for normal runs.
+    pub category: Option<String>,
+    pub towers: Vec<TowerType>,
+    /// Every action taken in the run, along with its seed, mode, map and waves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<Replay>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewPlayer {
+    name: String,
+}
+
+/// Responses that have arrived from the server, waiting to be picked up by `poll`.
+#[derive(Debug, Default)]
+struct Responses {
+    player: Option<String>,
+    scores: Option<Vec<ServerScore>>,
+}
+
+#[derive(Resource, Debug)]
+pub struct HttpLeaderboard {
+    url: String,
+    player: Option<String>,
+    scores: Vec<ServerScore>,
+    responses: Arc<Mutex<Responses>>,
+}
+impl HttpLeaderboard {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            player: None,
+            scores: Vec::new(),
+            responses: Default::default(),
+        }
+    }
+}
+
+impl LeaderboardBackend for HttpLeaderboard {
+    fn create_player(&mut self) {
+        let url = format!("{}/players", self.url);
+        let responses = self.responses.clone();
+        IoTaskPool::get()
+            .spawn(async move {
+                let player = ureq::post(&url)
+                    .send_json(ureq::json!({}))
+                    .map_err(|e| e.to_string())
+                    .and_then(|response| {
+                        response.into_json::<NewPlayer>().map_err(|e| e.to_string())
+                    });
+                match player {
+                    Ok(player) => responses.lock().unwrap().player = Some(player.name),
+                    Err(error) => warn!("Could not create a player at {}: {}", url, error),
+                }
+            })
+            .detach();
+    }
+
+    fn player_name(&self) -> Option<String> {
+        self.player.clone()
+    }
+
+    fn send_score(&mut self, run: &RunScore) {
+        let url = format!("{}/scores", self.url);
+        let score = ServerScore {
+            player: self.player.clone(),
+            score: run.score,
+            wave: run.wave,
+            seed: run.seed,
+            category: score_category(run.mode),
+            towers: run.towers.clone(),
+            replay: run.replay.clone(),
+        };
+        IoTaskPool::get()
+            .spawn(async move {
+                if let Err(error) = ureq::post(&url).send_json(&score) {
+                    warn!("Could not send the score to {}: {}", url, error);
+                }
+            })
+            .detach();
+    }
+
+    fn refresh_leaderboard(&mut self) {
+        let url = format!("{}/scores", self.url);
+        let responses = self.responses.clone();
+        IoTaskPool::get()
+            .spawn(async move {
+                let scores =
+                    ureq::get(&url)
+                        .call()
+                        .map_err(|e| e.to_string())
+                        .and_then(|response| {
+                            response
+                                .into_json::<Vec<ServerScore>>()
+                                .map_err(|e| e.to_string())
+                        });
+                match scores {
+                    Ok(scores) => responses.lock().unwrap().scores = Some(scores),
+                    Err(error) => warn!("Could not get the leaderboard from {}: {}", url, error),
+                }
+            })
+            .detach();
+    }
+
+    fn get_leaderboard(&self, category: &Option<String>) -> Vec<LeaderboardEntry> {
+        let mut scores: Vec<_> = self
+            .scores
+            .iter()
+            .filter(|score| score.category == *category)
+            .collect();
+        scores.sort_by_key(|score| Reverse(score.score));
+        scores
+            .into_iter()
+            .map(|score| LeaderboardEntry {
+                player: score
+                    .player
+                    .clone()
+                    .unwrap_or_else(|| "Anonymous".to_string()),
+                score: score.score,
+                meta: format!("Wave {}", score.wave),
+            })
+            .collect()
+    }
+
+    fn poll(&mut self) -> bool {
+        let mut responses = self.responses.lock().unwrap();
+        let mut changed = false;
+        if let Some(player) = responses.player.take() {
+            self.player = Some(player);
+            changed = true;
+        }
+        if let Some(scores) = responses.scores.take() {
+            self.scores = scores;
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use bevy::tasks::TaskPool;
+    use ureq::serde_json;
+
+    use crate::{
+        actions::PlayerAction, gameplay::RunMode, grid::map_file::MapData, replay::RecordedAction,
+        waves::WaveList,
+    };
+
+    use super::*;
+
+    /// A request the mock server received.
+    struct Request {
+        method: String,
+        path: String,
+        body: String,
+    }
+
+    /// Answer requests on a local port: stored scores for `GET /scores`, and nothing for anything
+    /// else. Every request is passed on to be checked.
+    fn mock_server(scores: Vec<ServerScore>) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&mut stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = match (method.as_str(), path.as_str()) {
+                    ("GET", "/scores") => serde_json::to_string(&scores).unwrap(),
+                    _ => String::new(),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+                let body = String::from_utf8(body).unwrap();
+                if sender.send(Request { method, path, body }).is_err() {
+                    break;
+                }
+            }
+        });
+        (url, receiver)
+    }
+
+    fn replay(seed: u64, mode: RunMode) -> Replay {
+        Replay {
+            seed,
+            mode,
+            map: MapData {
+                name: "Test".to_string(),
+                difficulty: Default::default(),
+                tiles: vec![vec![1, 1]],
+                spawns: vec![(-1, 0)],
+                mode: Default::default(),
+                exits: Vec::new(),
+                waves: None,
+            },
+            waves: WaveList {
+                waves: Vec::new(),
+                endless: Default::default(),
+            },
+            actions: vec![RecordedAction {
+                tick: 12,
+                action: PlayerAction::StartWave,
+            }],
+            ticks: 300,
+            score: 40,
+            wave_reached: 2,
+        }
+    }
+
+    fn score(player: &str, score: u32, category: Option<&str>) -> ServerScore {
+        ServerScore {
+            player: Some(player.to_string()),
+            score,
+            wave: 1,
+            seed: 0,
+            category: category.map(str::to_string),
+            towers: Vec::new(),
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn leaderboard_shows_one_category_best_first() {
+        let mut leaderboard = HttpLeaderboard::new("http://localhost".to_string());
+        leaderboard.scores = vec![
+            score("a", 10, None),
+            score("b", 50, Some("Daily 2024-01-01")),
+            score("c", 30, None),
+            score("d", 20, Some("Daily 2024-01-02")),
+            score("e", 20, None),
+        ];
+
+        let normal: Vec<_> = leaderboard
+            .get_leaderboard(&None)
+            .into_iter()
+            .map(|entry| (entry.player, entry.score))
+            .collect();
+        assert_eq!(
+            normal,
+            [
+                ("c".to_string(), 30),
+                ("e".to_string(), 20),
+                ("a".to_string(), 10)
+            ]
+        );
+
+        let daily = leaderboard.get_leaderboard(&Some("Daily 2024-01-02".to_string()));
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].player, "d");
+        assert!(leaderboard
+            .get_leaderboard(&Some("Daily 2024-01-03".to_string()))
+            .is_empty());
+    }
+
+    #[test]
+    fn scores_go_to_and_come_from_the_server() {
+        IoTaskPool::get_or_init(TaskPool::new);
+        let category = score_category(RunMode::Daily(19723));
+        let stored = ServerScore {
+            replay: None,
+            ..score("someone", 60, category.as_deref())
+        };
+        let (url, requests) = mock_server(vec![stored, score("else", 90, None)]);
+        let mut leaderboard = HttpLeaderboard::new(url);
+
+        leaderboard.send_score(&RunScore {
+            score: 40,
+            wave: 2,
+            seed: 5,
+            mode: RunMode::Daily(19723),
+            towers: vec![TowerType::Laser],
+            replay: Some(replay(5, RunMode::Daily(19723))),
+        });
+        let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/scores")
+        );
+        let sent: ServerScore = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(sent.score, 40);
+        assert_eq!(sent.seed, 5);
+        assert_eq!(sent.category, category);
+        assert_eq!(sent.towers, [TowerType::Laser]);
+        let sent_replay = sent
+            .replay
+            .expect("the replay should be sent with the score");
+        assert_eq!(sent_replay.seed, 5);
+        assert_eq!(sent_replay.mode, RunMode::Daily(19723));
+        assert_eq!(sent_replay.ticks, 300);
+        assert_eq!(sent_replay.actions.len(), 1);
+        assert_eq!(sent_replay.actions[0].action, PlayerAction::StartWave);
+
+        leaderboard.refresh_leaderboard();
+        let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("GET", "/scores")
+        );
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !leaderboard.poll() {
+            assert!(Instant::now() < deadline, "the scores never arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let daily = leaderboard.get_leaderboard(&category);
+        assert_eq!(daily.len(), 1);
+        assert_eq!((daily[0].player.as_str(), daily[0].score), ("someone", 60));
+        let normal = leaderboard.get_leaderboard(&None);
+        assert_eq!((normal[0].player.as_str(), normal[0].score), ("else", 90));
+    }
+}
@@ -0,0 +1,51 @@
+//! The online leaderboard hosted by Jornet.
+
+use bevy_jornet::{Leaderboard, Score};
+
+use super::{score_category, LeaderboardBackend, LeaderboardEntry, RunScore};
+
+/// Jornet only stores a score and a line of text with it, so the category goes at the start of
+/// the text.
+fn in_category(score: &Score, category: &Option<String>) -> bool {
+    let meta = score.meta.as_deref().unwrap_or("");
+    match category {
+        Some(category) => meta.starts_with(category.as_str()),
+        None => !meta.starts_with("Daily "),
+    }
+}
+
+impl LeaderboardBackend for Leaderboard {
+    fn create_player(&mut self) {
+        Leaderboard::create_player(self, None);
+    }
+
+    fn player_name(&self) -> Option<String> {
+        self.get_player().map(|player| player.name.clone())
+    }
+
+    fn send_score(&mut self, run: &RunScore) {
+        let meta = match score_category(run.mode) {
+            Some(category) => format!("{}: Wave {}", category, run.wave),
+            None => format!("Wave {}", run.wave),
+        };
+        self.send_score_with_meta(run.score as f32, &meta);
+    }
+
+    fn refresh_leaderboard(&mut self) {
+        Leaderboard::refresh_leaderboard(self);
+    }
+
+    fn get_leaderboard(&self, category: &Option<String>) -> Vec<LeaderboardEntry> {
+        let mut scores = Leaderboard::get_leaderboard(self);
+        scores.retain(|s| in_category(s, category));
+        scores.sort_unstable_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        scores
+            .into_iter()
+            .map(|s| LeaderboardEntry {
+                player: s.player,
+                score: s.score as u32,
+                meta: s.meta.unwrap_or_default(),
+            })
+            .collect()
+    }
+}
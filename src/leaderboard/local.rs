@@ -0,0 +1,190 @@
+//! A leaderboard of the best runs played on this machine, kept in storage between sessions.
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{daily, gameplay::RunMode, storage, tower::TowerType};
+
+use super::{loadout, score_category, LeaderboardBackend, LeaderboardEntry, RunScore};
+
+/// How many runs are kept in each category.
+const LOCAL_RUNS_KEPT: usize = 20;
+
+/// A run in the local leaderboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalRun {
+    pub score: u32,
+    pub wave: u32,
+    pub seed: u64,
+    pub mode: RunMode,
+    /// The day the run was played, counted from the Unix epoch.
+    pub day: u64,
+    /// The towers on the map when the run ended.
+    pub towers: Vec<TowerType>,
+}
+
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct LocalLeaderboard {
+    /// Every category's runs, best first.
+    pub runs: Vec<LocalRun>,
+    /// Where the last run sent ranked in its category, if it was kept.
+    #[serde(skip)]
+    last_rank: Option<usize>,
+}
+impl LocalLeaderboard {
+    /// Add a run, keeping only the best runs in each category. Returns the run's rank within its
+    /// category, if it was good enough to be kept.
+    pub fn add(&mut self, run: LocalRun) -> Option<usize> {
+        let category = score_category(run.mode);
+        // Runs that tie with an earlier one rank below it
+        let rank = self
+            .runs_in(&category)
+            .filter(|other| other.score >= run.score)
+            .count();
+        let index = self
+            .runs
+            .iter()
+            .position(|other| other.score < run.score)
+            .unwrap_or(self.runs.len());
+        self.runs.insert(index, run);
+        let mut counts = HashMap::new();
+        self.runs.retain(|run| {
+            let count = counts.entry(score_category(run.mode)).or_insert(0);
+            *count += 1;
+            *count <= LOCAL_RUNS_KEPT
+        });
+        (rank < LOCAL_RUNS_KEPT).then_some(rank)
+    }
+
+    pub fn runs_in<'a>(
+        &'a self,
+        category: &'a Option<String>,
+    ) -> impl Iterator<Item = &'a LocalRun> + 'a {
+        self.runs
+            .iter()
+            .filter(move |run| score_category(run.mode) == *category)
+    }
+}
+
+impl LeaderboardBackend for LocalLeaderboard {
+    /// Every run here was played on this machine, so there's no player to set up.
+    fn create_player(&mut self) {}
+
+    fn player_name(&self) -> Option<String> {
+        None
+    }
+
+    fn send_score(&mut self, run: &RunScore) {
+        self.last_rank = self.add(LocalRun {
+            score: run.score,
+            wave: run.wave,
+            seed: run.seed,
+            mode: run.mode,
+            day: daily::today(),
+            towers: run.towers.clone(),
+        });
+        save(self);
+    }
+
+    fn refresh_leaderboard(&mut self) {}
+
+    fn get_leaderboard(&self, category: &Option<String>) -> Vec<LeaderboardEntry> {
+        self.runs_in(category)
+            .map(|run| LeaderboardEntry {
+                player: daily::date(run.day),
+                score: run.score,
+                meta: format!(
+                    "Wave {}, seed {}: {}",
+                    run.wave,
+                    run.seed,
+                    loadout(&run.towers)
+                ),
+            })
+            .collect()
+    }
+
+    fn rank(&self, _run: &RunScore) -> Option<usize> {
+        self.last_rank
+    }
+}
+
+const LOCAL_LEADERBOARD_FILE: &str = "leaderboard.ron";
+
+pub fn load() -> LocalLeaderboard {
+    match storage::load(LOCAL_LEADERBOARD_FILE) {
+        Ok(leaderboard) => leaderboard.unwrap_or_default(),
+        Err(error) => {
+            warn!("Could not load the local leaderboard: {}", error);
+            LocalLeaderboard::default()
+        }
+    }
+}
+
+fn save(leaderboard: &LocalLeaderboard) {
+    if let Err(error) = storage::save(LOCAL_LEADERBOARD_FILE, leaderboard) {
+        warn!("Could not save the local leaderboard: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(score: u32, mode: RunMode) -> LocalRun {
+        LocalRun {
+            score,
+            wave: 1,
+            seed: 0,
+            mode,
+            day: 0,
+            towers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn runs_are_ranked_within_their_category() {
+        let mut leaderboard = LocalLeaderboard::default();
+        assert_eq!(leaderboard.add(run(20, RunMode::Normal)), Some(0));
+        assert_eq!(leaderboard.add(run(30, RunMode::Daily(1))), Some(0));
+        assert_eq!(leaderboard.add(run(10, RunMode::Normal)), Some(1));
+        assert_eq!(leaderboard.add(run(40, RunMode::Normal)), Some(0));
+        // A tie ranks below the run it ties with
+        assert_eq!(leaderboard.add(run(20, RunMode::Normal)), Some(2));
+
+        let scores: Vec<_> = leaderboard.runs_in(&None).map(|run| run.score).collect();
+        assert_eq!(scores, [40, 20, 20, 10]);
+        assert_eq!(
+            leaderboard
+                .runs_in(&score_category(RunMode::Daily(1)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn only_the_best_runs_in_each_category_are_kept() {
+        let mut leaderboard = LocalLeaderboard::default();
+        for score in 0..LOCAL_RUNS_KEPT as u32 {
+            leaderboard.add(run(score + 10, RunMode::Normal));
+        }
+        leaderboard.add(run(5, RunMode::Daily(1)));
+
+        assert_eq!(leaderboard.add(run(0, RunMode::Normal)), None);
+        assert_eq!(
+            leaderboard.add(run(100, RunMode::Normal)),
+            Some(0),
+            "a better run pushes out the worst"
+        );
+        let scores: Vec<_> = leaderboard.runs_in(&None).map(|run| run.score).collect();
+        assert_eq!(scores.len(), LOCAL_RUNS_KEPT);
+        assert_eq!(scores.first(), Some(&100));
+        assert_eq!(scores.last(), Some(&11));
+        // Filling one category doesn't push runs out of another
+        assert_eq!(
+            leaderboard
+                .runs_in(&score_category(RunMode::Daily(1)))
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Where the results of runs are sent, and where leaderboards come from. The results screen
+//! works with any [`LeaderboardBackend`], and [`LeaderboardConfig`] picks which one is used.
+
+use bevy::prelude::*;
+
+use crate::{daily, gameplay::RunMode, replay::Replay, tower::TowerType};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+pub mod jornet;
+pub mod local;
+
+/// The result of a run, as it's sent to a leaderboard.
+#[derive(Debug, Clone)]
+pub struct RunScore {
+    pub score: u32,
+    pub wave: u32,
+    pub seed: u64,
+    pub mode: RunMode,
+    /// The towers on the map when the run ended.
+    pub towers: Vec<TowerType>,
+    /// The run's replay, for backends that check a score by playing it again.
+    pub replay: Option<Replay>,
+}
+
+/// A score on a leaderboard, as the text shown in each column.
+#[derive(Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub player: String,
+    pub score: u32,
+    pub meta: String,
+}
+
+/// Daily challenge scores are tagged with the date, so they can be ranked separately from
+/// normal runs.
+pub fn score_category(mode: RunMode) -> Option<String> {
+    match mode {
+        RunMode::Normal => None,
+        RunMode::Daily(day) => Some(format!("Daily {}", daily::date(day))),
+    }
+}
+
+/// The towers in a run, as how many there were of each type.
+pub fn loadout(towers: &[TowerType]) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for tower in towers {
+        match counts.iter_mut().find(|(name, _)| *name == tower.name()) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((tower.name(), 1)),
+        }
+    }
+    if counts.is_empty() {
+        return "None".to_string();
+    }
+    counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A leaderboard the game can send scores to. Backends are resources, and should only be
+/// marked as changed once there are new scores to show.
+pub trait LeaderboardBackend: Resource {
+    /// Set up the player whose scores this game sends.
+    fn create_player(&mut self);
+
+    /// The name the player's scores are sent under, once the player has been set up.
+    fn player_name(&self) -> Option<String>;
+
+    fn send_score(&mut self, run: &RunScore);
+
+    /// Start fetching the latest scores, for backends that fetch them from elsewhere.
+    fn refresh_leaderboard(&mut self);
+
+    /// The scores in a category, best first. `None` is the category for normal runs.
+    fn get_leaderboard(&self, category: &Option<String>) -> Vec<LeaderboardEntry>;
+
+    /// Pick up anything that's arrived since the last frame. Returns whether there are new
+    /// scores to show.
+    fn poll(&mut self) -> bool {
+        false
+    }
+
+    /// Where a run ranks in its category's leaderboard, if it's on it.
+    fn rank(&self, run: &RunScore) -> Option<usize> {
+        let player = self.player_name()?;
+        self.get_leaderboard(&score_category(run.mode))
+            .iter()
+            .position(|entry| entry.player == player && entry.score == run.score)
+    }
+}
+
+/// Which leaderboard backend to use.
+#[derive(Debug, Clone)]
+pub enum LeaderboardConfig {
+    Jornet {
+        id: &'static str,
+        key: &'static str,
+    },
+    /// A score server of our own, at the given base URL.
+    #[cfg(not(target_arch = "wasm32"))]
+    Http {
+        url: String,
+    },
+    Local,
+}
+impl LeaderboardConfig {
+    /// Setting `LEADERBOARD_URL`, either when the game is run or when it's built, points it at a
+    /// score server. Otherwise Jornet is used if `JORNET_ID` and `JORNET_KEY` were set when the
+    /// game was built, or else scores are only kept locally.
+    pub fn from_env() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let url = std::env::var("LEADERBOARD_URL")
+                .ok()
+                .or_else(|| option_env!("LEADERBOARD_URL").map(str::to_string))
+                .filter(|url| !url.is_empty());
+            if let Some(url) = url {
+                return Self::Http {
+                    url: url.trim_end_matches('/').to_string(),
+                };
+            }
+        }
+        match (option_env!("JORNET_ID"), option_env!("JORNET_KEY")) {
+            (Some(id), Some(key)) => Self::Jornet { id, key },
+            _ => Self::Local,
+        }
+    }
+}
+
+pub fn create_player<B: LeaderboardBackend>(mut backend: ResMut<B>) {
+    backend.create_player();
+}
+
+pub fn poll_leaderboard<B: LeaderboardBackend>(mut backend: ResMut<B>) {
+    if backend.bypass_change_detection().poll() {
+        backend.set_changed();
+    }
+}
@@ -0,0 +1,28 @@
+//! Everything but the game's entry point, so tools can play runs headlessly without opening a
+//! window. The `verify` binary uses this to check replays the way a score server would.
+
+#![allow(
+    clippy::too_many_arguments,
+    clippy::type_complexity,
+    clippy::collapsible_match
+)]
+
+pub mod actions;
+pub mod audio;
+pub mod daily;
+pub mod enemies;
+pub mod gameplay;
+pub mod grid;
+pub mod input;
+pub mod leaderboard;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod settings;
+pub mod simulation;
+pub mod state;
+pub mod storage;
+pub mod tower;
+pub mod ui;
+pub mod verify;
+pub mod waves;
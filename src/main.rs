@@ -1,28 +1,38 @@
-#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+use std::path::Path;
 
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::{AudioApp, AudioPlugin};
 
-use audio::{AudioAssets, DrumsChannel, MusicChannel, SoundChannel};
-use state::{
-    game::GamePlugin, loading::GameAssets, main_menu::MainMenuPlugin, results::ResultsPlugin,
+use neon_breach::{
+    audio::{AudioAssets, DrumsChannel, MusicChannel, SoundChannel},
+    grid::map_file::{MapData, MapLoader},
+    replay, settings, simulation,
+    state::{
+        self, editor::EditorPlugin, game::GamePlugin, loading::GameAssets,
+        main_menu::MainMenuPlugin, replay::ReplayPlugin, results::ResultsPlugin,
+    },
+    ui::constants::BACKGROUND_COLOR,
+    waves::{WaveList, WaveLoader},
 };
-use ui::constants::BACKGROUND_COLOR;
-
-mod audio;
-mod enemies;
-mod gameplay;
-mod grid;
-mod input;
-mod state;
-mod tower;
-mod ui;
 
 fn main() {
+    // `--simulate <scenario>` plays a scenario headlessly and prints the results
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path] = &args[..] {
+        if flag == "--simulate" {
+            simulate(Path::new(path));
+            return;
+        }
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
         .add_plugins(AudioPlugin)
+        .init_asset::<MapData>()
+        .init_asset_loader::<MapLoader>()
+        .init_asset::<WaveList>()
+        .init_asset_loader::<WaveLoader>()
         .init_state::<state::State>()
         .add_loading_state(
             LoadingState::new(state::State::Loading)
@@ -30,16 +40,38 @@ fn main() {
                 .load_collection::<GameAssets>()
                 .load_collection::<AudioAssets>(),
         )
+        .add_plugins(settings::SettingsPlugin)
         .add_plugins(MainMenuPlugin)
         .add_plugins(GamePlugin)
         .add_plugins(ResultsPlugin)
-        .add_systems(Startup, state::results::create_player)
+        .add_plugins(EditorPlugin)
+        .add_plugins(ReplayPlugin)
+        .init_resource::<replay::LastReplay>()
         .add_systems(OnEnter(state::State::Loading), state::loading::setup)
         .add_systems(OnExit(state::State::Loading), state::loading::cleanup)
         .add_audio_channel::<MusicChannel>()
         .add_audio_channel::<DrumsChannel>()
         .add_audio_channel::<SoundChannel>()
-        .insert_resource(audio::VolumeSettings::default())
         .insert_resource(ClearColor(BACKGROUND_COLOR))
         .run();
 }
+
+fn simulate(path: &Path) {
+    let result = simulation::load_scenario(path)
+        .and_then(|scenario| simulation::run(&scenario, Path::new("assets")));
+    match result {
+        Ok(report) => {
+            println!("Seed: {}", report.seed);
+            println!("Wave reached: {}", report.wave_reached);
+            println!("Score: {}", report.score);
+            println!("Lives lost: {}", report.lives_lost);
+            if report.timed_out {
+                println!("Timed out: a wave took too long to clear");
+            }
+        }
+        Err(error) => {
+            eprintln!("Simulation failed: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Recording every action the player takes during a run, so the run can be saved to a replay
+//! file and played back exactly.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::PlayerAction,
+    gameplay::{GameManager, RunMode},
+    grid::{map_file::MapData, SelectedMap},
+    rng::GameRng,
+    storage::{self, StorageError},
+    waves::WaveList,
+};
+
+/// Where replays are saved in storage.
+pub const REPLAY_DIR: &str = "replays";
+/// The file the most recent run is always saved to, as well as its own file.
+pub const LAST_REPLAY: &str = "replays/last.replay.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedAction {
+    /// The tick the action was applied at.
+    pub tick: u64,
+    pub action: PlayerAction,
+}
+
+/// A run, as it is stored in a `.replay.ron` file. The map and waves are stored in full so the
+/// replay still works if the files it was played with change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub mode: RunMode,
+    pub map: MapData,
+    pub waves: WaveList,
+    pub actions: Vec<RecordedAction>,
+    /// The number of ticks the run lasted.
+    pub ticks: u64,
+    pub score: u32,
+    pub wave_reached: usize,
+}
+
+/// Load a replay file from anywhere on disk.
+pub fn load(path: &Path) -> Result<Replay, StorageError> {
+    storage::load_file(path)
+}
+
+/// Save a replay to [`LAST_REPLAY`], and to its own file in [`REPLAY_DIR`]. Returns where the
+/// replay's own file was stored.
+pub fn save(replay: &Replay) -> Result<String, StorageError> {
+    storage::save(LAST_REPLAY, replay)?;
+    // Local storage only holds a few megabytes, so the browser just keeps the last replay
+    if cfg!(target_arch = "wasm32") {
+        return Ok(LAST_REPLAY.to_string());
+    }
+    let timestamp = bevy::utils::SystemTime::now()
+        .duration_since(bevy::utils::SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let name = format!("{}/{}-{}.replay.ron", REPLAY_DIR, timestamp, replay.seed);
+    storage::save(&name, replay)?;
+    Ok(name)
+}
+
+/// The actions taken so far in the run being played.
+#[derive(Resource, Debug, Default)]
+pub struct Recording {
+    pub actions: Vec<RecordedAction>,
+    /// The waves the run started with, if they've been edited since. The edits themselves are
+    /// recorded as actions.
+    pub waves: Option<WaveList>,
+}
+
+/// The replay of the most recently finished run.
+#[derive(Resource, Debug, Default)]
+pub struct LastReplay(pub Option<Replay>);
+
+pub fn record_actions(
+    mut recording: ResMut<Recording>,
+    mut actions: EventReader<PlayerAction>,
+    game_manager: Res<GameManager>,
+) {
+    for action in actions.read() {
+        recording.actions.push(RecordedAction {
+            tick: game_manager.tick,
+            action: action.clone(),
+        });
+    }
+}
+
+/// Once a run is over, turn its recording into a replay and save it.
+pub fn finish_recording(
+    mut commands: Commands,
+    recording: Option<Res<Recording>>,
+    game_manager: Res<GameManager>,
+    rng: Res<GameRng>,
+    run_mode: Res<RunMode>,
+    selected_map: Res<SelectedMap>,
+    maps: Res<Assets<MapData>>,
+    mut last_replay: ResMut<LastReplay>,
+) {
+    let Some(recording) = recording else {
+        return;
+    };
+    commands.remove_resource::<Recording>();
+    if game_manager.lives > 0 {
+        return;
+    }
+    let Some(map) = maps.get(&selected_map.0) else {
+        return;
+    };
+    let replay = Replay {
+        seed: rng.seed(),
+        mode: *run_mode,
+        map: map.clone(),
+        waves: recording
+            .waves
+            .clone()
+            .unwrap_or_else(|| game_manager.wave_list()),
+        actions: recording.actions.clone(),
+        ticks: game_manager.tick,
+        score: game_manager.score,
+        wave_reached: game_manager.current_wave,
+    };
+    save_replay(&replay);
+    last_replay.0 = Some(replay);
+}
+
+fn save_replay(replay: &Replay) {
+    match save(replay) {
+        Ok(name) => info!("Saved replay to {}", name),
+        Err(error) => warn!("Could not save replay: {}", error),
+    }
+}
+
+/// The most recent replay: the last run finished this session, or else the last one saved.
+pub fn latest(last_replay: &LastReplay) -> Option<Replay> {
+    match &last_replay.0 {
+        Some(replay) => Some(replay.clone()),
+        None => load_last(),
+    }
+}
+
+fn load_last() -> Option<Replay> {
+    match storage::load(LAST_REPLAY) {
+        Ok(replay) => replay,
+        Err(error) => {
+            warn!("Could not load the last replay: {}", error);
+            None
+        }
+    }
+}
+
+/// Fast-forwarding to a point in a replay runs the game this much faster than normal.
+const SEEK_SPEED: f32 = 100.0;
+
+/// A replay being watched.
+#[derive(Resource, Debug)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    /// The index of the next action to play.
+    pub next_action: usize,
+    pub paused: bool,
+    /// How much faster than the original run the replay is played.
+    pub speed: f32,
+    /// The tick being fast-forwarded to, if any.
+    pub seek: Option<u64>,
+}
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next_action: 0,
+            paused: false,
+            speed: 1.0,
+            seek: None,
+        }
+    }
+
+    /// Whether the game should play the given tick.
+    pub fn should_tick(&self, tick: u64) -> bool {
+        if tick >= self.replay.ticks {
+            return false;
+        }
+        match self.seek {
+            Some(target) => tick < target,
+            None => !self.paused,
+        }
+    }
+
+    /// Whether the replay has reached the end of the run.
+    pub fn finished(&self, tick: u64) -> bool {
+        tick >= self.replay.ticks
+    }
+
+    /// The relative speed to run the game at, given whether the run was at fast speed.
+    pub fn time_speed(&self, fast: bool) -> f32 {
+        if self.seek.is_some() {
+            SEEK_SPEED
+        } else if fast {
+            crate::actions::FAST_SPEED * self.speed
+        } else {
+            self.speed
+        }
+    }
+}
+
+/// Run condition for the gameplay systems. While a replay is being watched, the game only moves
+/// on when the viewer isn't paused and the run hasn't ended.
+pub fn replay_ticking(
+    playback: Option<Res<ReplayPlayback>>,
+    game_manager: Option<Res<GameManager>>,
+) -> bool {
+    match (playback, game_manager) {
+        (Some(playback), Some(game_manager)) => playback.should_tick(game_manager.tick),
+        _ => true,
+    }
+}
+
+/// Send the recorded actions for this tick as if the player had just taken them.
+pub fn play_actions(
+    mut playback: ResMut<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    let playback = &mut *playback;
+    while let Some(recorded) = playback.replay.actions.get(playback.next_action) {
+        if recorded.tick > game_manager.tick {
+            break;
+        }
+        actions.send(recorded.action.clone());
+        playback.next_action += 1;
+    }
+}
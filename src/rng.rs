@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+/// Mixed into the seed for the tower offers stream, so it doesn't match the main stream.
+const OFFERS_STREAM: u64 = 0x6F66_6665_7273;
+
+/// Mixed into the seed, along with the wave, when a run saved before the generator's state was
+/// kept is picked up again.
+const RESUME_STREAM: u64 = 0x7265_7375_6D65;
+
+/// The seed picked on the main menu for the next run. `None` picks a random seed.
+#[derive(Resource, Debug, Default)]
+pub struct SelectedSeed(pub Option<u64>);
+
+/// The random number generator for a run. Everything random in a run is drawn from this, so
+/// playing the same seed the same way always gives the same result. The streams are the same
+/// generator as `StdRng`, but can be saved along with a run and carry on where they left off.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha12Rng,
+    offers: ChaCha12Rng,
+}
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            offers: ChaCha12Rng::seed_from_u64(seed ^ OFFERS_STREAM),
+        }
+    }
+
+    /// The generator for a run saved before the generator's state was kept, picked up again
+    /// before the given wave. Where the streams had got to isn't known, so they start again from
+    /// the seed mixed with the wave instead.
+    pub fn resumed(seed: u64, wave: usize) -> Self {
+        let mixed = seed ^ RESUME_STREAM.wrapping_mul(wave as u64 + 1);
+        Self {
+            seed,
+            ..Self::new(mixed)
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The generator for the towers offered between waves. It's kept separate from everything
+    /// else, so the same seed offers the same towers however the run is played.
+    pub fn offers(&mut self) -> &mut ChaCha12Rng {
+        &mut self.offers
+    }
+}
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn saved_generator_carries_on_where_it_left_off() {
+        let mut rng = GameRng::new(7);
+        rng.gen::<u64>();
+        rng.offers().gen::<u64>();
+        let saved = ron::to_string(&rng).unwrap();
+        let mut resumed: GameRng = ron::from_str(&saved).unwrap();
+        assert_eq!(resumed.seed(), 7);
+        assert_eq!(resumed.gen::<u64>(), rng.gen::<u64>());
+        assert_eq!(resumed.offers().gen::<u64>(), rng.offers().gen::<u64>());
+    }
+}
@@ -0,0 +1,284 @@
+//! Saving a run between waves, so it can be picked up again from the main menu after the game
+//! is closed.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::PlayerAction,
+    gameplay::{GameManager, RunMode, WaveState, AUTO_START_SECONDS},
+    grid::{map_file::MapData, Map, SelectedMap},
+    replay::{RecordedAction, Recording},
+    rng::GameRng,
+    state::loading::GameAssets,
+    storage,
+    tower::{
+        debuffs::{OverheatIcon, Overheatable},
+        laser::{Direction, Laser},
+        spawn_tower_entity, Tower,
+    },
+    ui::{inventory::Inventory, tower_options::present_tower_options, UiState, UiStateResource},
+    waves::WaveList,
+};
+
+/// Where the saved run is kept in storage.
+pub const SAVE_FILE: &str = "saves/run.save.ron";
+
+/// How far an overheating tower's timer has run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverheatTimer {
+    pub elapsed: f32,
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTower {
+    pub pos: (i8, i8),
+    /// The tower's base stats and upgrades. What the towers around it do to it is worked out
+    /// again once it's back on the map.
+    pub tower: Tower,
+    /// Which way the tower is facing, if it's a laser.
+    pub direction: Option<Direction>,
+    pub overheat: Option<OverheatTimer>,
+}
+
+/// A run, as it is stored in a `.save.ron` file. Runs are only saved between waves, so there
+/// are never any enemies or projectiles to store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRun {
+    pub seed: u64,
+    pub mode: RunMode,
+    pub map: MapData,
+    pub waves: WaveList,
+    pub tick: u64,
+    /// The next wave to be played.
+    pub current_wave: usize,
+    pub lives: u16,
+    pub score: u32,
+    #[serde(default)]
+    pub upgrade_points: u32,
+    pub health_multiplier: f32,
+    pub speed_multiplier: f32,
+    pub auto_start: bool,
+    pub towers: Vec<SavedTower>,
+    pub inventory: Vec<Tower>,
+    /// The towers on offer, if the player hadn't picked one yet.
+    pub offers: Vec<Tower>,
+    /// Offers still to come for waves that were called early.
+    #[serde(default)]
+    pub owed_offers: u32,
+    /// Where the random number generator had got to. Runs saved before it was kept don't have
+    /// it, and can only be picked up again off the record.
+    #[serde(default)]
+    pub rng: Option<GameRng>,
+    /// Every action taken in the run so far, so it keeps being recorded once it's picked up again.
+    #[serde(default)]
+    pub actions: Vec<RecordedAction>,
+    /// The waves the run started with, if they've been edited since.
+    #[serde(default)]
+    pub recorded_waves: Option<WaveList>,
+}
+
+/// Whether there's a saved run to continue.
+pub fn has_save() -> bool {
+    storage::exists(SAVE_FILE)
+}
+
+/// Load the saved run. A save that can't be read won't get any better, so it's deleted.
+pub fn load_saved() -> Option<SavedRun> {
+    match storage::load::<SavedRun>(SAVE_FILE) {
+        Ok(run) => run.map(|mut run| {
+            let towers = run.towers.iter_mut().map(|saved| &mut saved.tower);
+            for tower in towers.chain(&mut run.inventory).chain(&mut run.offers) {
+                tower.fill_missing_base_stats();
+            }
+            run
+        }),
+        Err(error) => {
+            warn!("Could not load saved run, deleting it: {}", error);
+            delete_save();
+            None
+        }
+    }
+}
+
+fn write_save(run: &SavedRun) {
+    if let Err(error) = storage::save(SAVE_FILE, run) {
+        warn!("Could not save run: {}", error);
+    }
+}
+
+fn delete_save() {
+    if let Err(error) = storage::remove(SAVE_FILE) {
+        warn!("Could not delete saved run: {}", error);
+    }
+}
+
+/// A saved run to pick up when the game starts, instead of starting a new one.
+#[derive(Resource, Debug)]
+pub struct ResumeRun(pub SavedRun);
+
+/// Save the run whenever it's between waves and something has changed: a wave has just ended,
+/// or the player has picked, placed or turned a tower.
+pub fn autosave(
+    mut actions: EventReader<PlayerAction>,
+    game_manager: Res<GameManager>,
+    ui_state: Res<UiStateResource>,
+    inventory: Res<Inventory>,
+    map: Res<Map>,
+    towers: Query<(&Tower, Option<&Laser>, Option<&Overheatable>)>,
+    rng: Res<GameRng>,
+    recording: Option<Res<Recording>>,
+    run_mode: Res<RunMode>,
+    selected_map: Res<SelectedMap>,
+    maps: Res<Assets<MapData>>,
+) {
+    let acted = actions.read().count() > 0;
+    if !matches!(game_manager.wave_state, WaveState::Waiting)
+        || game_manager.current_wave == 0
+        || !(acted || ui_state.is_changed())
+    {
+        return;
+    }
+    let Some(map_data) = maps.get(&selected_map.0) else {
+        return;
+    };
+    let mut placements: Vec<_> = map.placements.iter().collect();
+    placements.sort_by_key(|(pos, _)| **pos);
+    let towers = placements
+        .into_iter()
+        .filter_map(|(pos, entity)| {
+            let (tower, laser, overheatable) = towers.get(*entity).ok()?;
+            Some(SavedTower {
+                pos: *pos,
+                tower: tower.clone(),
+                direction: laser.map(|laser| laser.direction),
+                overheat: overheatable.map(|overheatable| OverheatTimer {
+                    elapsed: overheatable.0.elapsed_secs(),
+                    duration: overheatable.0.duration().as_secs_f32(),
+                }),
+            })
+        })
+        .collect();
+    let offers = match &ui_state.state {
+        UiState::PickingTower(options) => options.clone(),
+        _ => Vec::new(),
+    };
+    write_save(&SavedRun {
+        seed: rng.seed(),
+        mode: *run_mode,
+        map: map_data.clone(),
+        waves: game_manager.wave_list(),
+        tick: game_manager.tick,
+        current_wave: game_manager.current_wave,
+        lives: game_manager.lives,
+        score: game_manager.score,
+        upgrade_points: game_manager.upgrade_points,
+        health_multiplier: game_manager.health_multiplier,
+        speed_multiplier: game_manager.speed_multiplier,
+        auto_start: game_manager.countdown.is_some(),
+        towers,
+        inventory: inventory.towers.clone(),
+        offers,
+        owed_offers: game_manager.owed_offers,
+        // A run that isn't being recorded stays that way
+        rng: recording.is_some().then(|| rng.clone()),
+        recorded_waves: recording
+            .as_ref()
+            .and_then(|recording| recording.waves.clone()),
+        actions: recording.map_or_else(Vec::new, |recording| recording.actions.clone()),
+    });
+}
+
+/// A run that's over can't be continued.
+pub fn discard_finished_run(game_manager: Res<GameManager>) {
+    if game_manager.lives == 0 {
+        delete_save();
+    }
+}
+
+/// Put a saved run back the way it was. The towers' modifiers are worked out from the map on
+/// the next tick, so only the overheat timers need restoring.
+pub fn resume_run(
+    mut commands: Commands,
+    resume: Option<Res<ResumeRun>>,
+    mut game_manager: ResMut<GameManager>,
+    mut inventory: ResMut<Inventory>,
+    mut ui_state: ResMut<UiStateResource>,
+    mut map: ResMut<Map>,
+    game_assets: Res<GameAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Some(resume) = resume else {
+        return;
+    };
+    commands.remove_resource::<ResumeRun>();
+    let run = &resume.0;
+    match &run.rng {
+        Some(rng) => {
+            commands.insert_resource(rng.clone());
+            commands.insert_resource(Recording {
+                actions: run.actions.clone(),
+                waves: run.recorded_waves.clone(),
+            });
+        }
+        None => commands.insert_resource(GameRng::resumed(run.seed, run.current_wave)),
+    }
+
+    game_manager.tick = run.tick;
+    game_manager.current_wave = run.current_wave;
+    game_manager.lives = run.lives;
+    game_manager.score = run.score;
+    game_manager.upgrade_points = run.upgrade_points;
+    game_manager.owed_offers = run.owed_offers;
+    game_manager.health_multiplier = run.health_multiplier;
+    game_manager.speed_multiplier = run.speed_multiplier;
+    if run.auto_start {
+        game_manager.countdown = Some(Timer::from_seconds(AUTO_START_SECONDS, TimerMode::Once));
+    }
+    let spawns = map.spawns.len();
+    game_manager.prepare_wave(run.current_wave, spawns);
+    inventory.towers = run.inventory.clone();
+
+    for saved in run.towers.iter() {
+        if !map.is_valid_placement(saved.pos) {
+            warn!("Saved tower at {:?} can't be placed", saved.pos);
+            continue;
+        }
+        let entity = spawn_tower_entity(
+            saved.tower.clone(),
+            &mut commands,
+            saved.pos,
+            saved.direction.unwrap_or(Direction::Down),
+            &game_assets,
+            &mut meshes,
+            &mut materials,
+            &map,
+        );
+        map.place_tower(saved.pos, entity).unwrap();
+        if let Some(overheat) = &saved.overheat {
+            let mut timer = Timer::from_seconds(overheat.duration, TimerMode::Once);
+            timer.set_elapsed(Duration::from_secs_f32(overheat.elapsed));
+            commands.entity(entity).insert(Overheatable(timer));
+        }
+        if saved.tower.overheating {
+            commands.entity(entity).with_children(|parent| {
+                parent
+                    .spawn(SpriteBundle {
+                        texture: game_assets.overheat.clone(),
+                        transform: Transform::from_xyz(0.0, 0.0, 4.0),
+                        ..Default::default()
+                    })
+                    .insert(OverheatIcon);
+            });
+        }
+    }
+
+    if !run.offers.is_empty() {
+        ui_state.state = UiState::PickingTower(run.offers.clone());
+        present_tower_options(commands, game_assets.font.clone(), &run.offers);
+    }
+}
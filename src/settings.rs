@@ -0,0 +1,112 @@
+//! Settings and the high score, kept between sessions.
+
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioChannel, AudioControl};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{MusicChannel, SoundChannel, VolumeSettings},
+    state::results::Scores,
+    storage,
+};
+
+/// What's stored between sessions. Anything missing from an older file is left at its default,
+/// so new settings can be added here without breaking existing files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub sfx_vol: f64,
+    pub music_vol: f64,
+    pub high_score: u32,
+    /// Whether runs start at fast speed, as the player last left it.
+    pub fast_speed: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        let volume = VolumeSettings::default();
+        Self {
+            sfx_vol: volume.sfx_vol,
+            music_vol: volume.music_vol,
+            high_score: 0,
+            fast_speed: false,
+        }
+    }
+}
+
+/// Loads the settings when the app is built, so they're in place before any menus are drawn,
+/// and saves them whenever they change.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = load();
+        app.insert_resource(VolumeSettings {
+            sfx_vol: settings.sfx_vol,
+            music_vol: settings.music_vol,
+        })
+        .insert_resource(Scores {
+            high_score: settings.high_score,
+            ..Default::default()
+        })
+        .insert_resource(SpeedPreference(settings.fast_speed))
+        .insert_resource(SavedSettings(settings))
+        .add_systems(Startup, apply_volume)
+        .add_systems(Update, save_settings);
+    }
+}
+
+/// Whether the player last picked fast speed during a run, so the next run can start that way.
+#[derive(Resource)]
+pub struct SpeedPreference(pub bool);
+
+/// The settings as they were last loaded or saved.
+#[derive(Resource)]
+struct SavedSettings(Settings);
+
+fn apply_volume(
+    volume_settings: Res<VolumeSettings>,
+    music_channel: Res<AudioChannel<MusicChannel>>,
+    sound_channel: Res<AudioChannel<SoundChannel>>,
+) {
+    music_channel.set_volume(volume_settings.music_vol);
+    sound_channel.set_volume(volume_settings.sfx_vol);
+}
+
+fn save_settings(
+    volume_settings: Res<VolumeSettings>,
+    scores: Res<Scores>,
+    speed_preference: Res<SpeedPreference>,
+    mut saved: ResMut<SavedSettings>,
+) {
+    if !volume_settings.is_changed() && !scores.is_changed() && !speed_preference.is_changed() {
+        return;
+    }
+    let settings = Settings {
+        sfx_vol: volume_settings.sfx_vol,
+        music_vol: volume_settings.music_vol,
+        high_score: scores.high_score,
+        fast_speed: speed_preference.0,
+    };
+    if settings != saved.0 {
+        store(&settings);
+        saved.0 = settings;
+    }
+}
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+fn load() -> Settings {
+    match storage::load(SETTINGS_FILE) {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(error) => {
+            warn!("Could not load settings: {}", error);
+            Settings::default()
+        }
+    }
+}
+
+fn store(settings: &Settings) {
+    if let Err(error) = storage::save(SETTINGS_FILE, settings) {
+        warn!("Could not save settings: {}", error);
+    }
+}
@@ -0,0 +1,474 @@
+//! Plays the game without a window, renderer or audio, so the balance of waves and towers can be
+//! checked quickly and without a GPU.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use bevy_kira_audio::AudioChannel;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    actions::PlayerAction,
+    audio::{AudioAssets, DrumsChannel, SoundChannel, VolumeSettings},
+    gameplay::{GameManager, GameplayPlugin, GameplaySet, RunMode, WaveState, TICKS_PER_SECOND},
+    grid::{
+        map_file::{MapData, MapError},
+        Map,
+    },
+    replay::{play_actions, record_actions, Recording, Replay, ReplayPlayback},
+    rng::GameRng,
+    state::loading::GameAssets,
+    tower::{debuffs::Debuff, spawn_tower, Tower, TowerPlaced, TowerType},
+    ui::{
+        inventory::Inventory, statusbar::GameSpeed, tower_options::TowerOptionsRoot, UiData,
+        UiState, UiStateResource,
+    },
+    waves::{WaveError, WaveGenerator, WaveList},
+};
+
+/// The game time to simulate before giving up on a wave, in seconds.
+const MAX_SECONDS_PER_WAVE: f32 = 600.0;
+
+/// A setup to simulate, as it is stored in a `.sim.ron` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Path of the map file, relative to the assets folder.
+    pub map: String,
+    /// Path of the waves file, relative to the assets folder. If not set, the map's waves are
+    /// used, or the default waves if the map doesn't have any.
+    #[serde(default)]
+    pub waves: Option<String>,
+    /// Towers placed before the first wave starts.
+    pub towers: Vec<TowerSetup>,
+    /// Take the first tower offered after each wave and place it on the first free tile.
+    /// Otherwise, towers offered between waves are turned down.
+    #[serde(default)]
+    pub take_offers: bool,
+    /// Stop once this many waves have been cleared.
+    pub max_waves: usize,
+    /// Seed for the run. If not set, a random seed is picked.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Seconds of game time that pass on each update.
+    #[serde(default = "default_timestep")]
+    pub timestep: f32,
+}
+
+fn default_timestep() -> f32 {
+    1.0 / 60.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TowerSetup {
+    pub pos: (i8, i8),
+    pub variant: TowerType,
+    pub damage: f32,
+    pub rate: f32,
+    pub debuff: Debuff,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub seed: u64,
+    pub wave_reached: usize,
+    pub score: u32,
+    pub lives_lost: u16,
+    /// Whether a wave took longer than [`MAX_SECONDS_PER_WAVE`], so the simulation was stopped.
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum SimulationError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("could not parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    #[error("invalid map: {0}")]
+    InvalidMap(#[from] MapError),
+    #[error("invalid waves: {0}")]
+    InvalidWaves(#[from] WaveError),
+    #[error("a tower can't be placed at {pos:?}")]
+    InvalidPlacement { pos: (i8, i8) },
+    #[error("towers placed by the scenario can't be recorded in a replay")]
+    Unrecordable,
+}
+
+fn read_ron<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, SimulationError> {
+    let bytes = std::fs::read(path).map_err(|source| SimulationError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    ron::de::from_bytes(&bytes).map_err(|source| SimulationError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub fn load_scenario(path: &Path) -> Result<Scenario, SimulationError> {
+    read_ron(path)
+}
+
+/// Towers still waiting to be placed.
+#[derive(Resource)]
+struct PendingTowers(VecDeque<TowerSetup>);
+
+/// Read a map and the waves to play on it. If no waves file is given, the map's waves are used,
+/// or the default waves if the map doesn't have any.
+pub fn load_map_and_waves(
+    assets_dir: &Path,
+    map_path: &str,
+    waves_path: Option<&str>,
+) -> Result<(MapData, WaveList), SimulationError> {
+    let map_data: MapData = read_ron(&assets_dir.join(map_path))?;
+    let waves_path = waves_path
+        .or(map_data.waves.as_deref())
+        .unwrap_or("waves/default.waves.ron");
+    let wave_list: WaveList = read_ron(&assets_dir.join(waves_path))?;
+    wave_list.validate()?;
+    Ok((map_data, wave_list))
+}
+
+/// Build an app that plays the game without a window, one tick per update.
+fn headless_app(map: Map, game_manager: GameManager, seed: u64, timestep: Duration) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin {
+            watch_for_changes_override: Some(false),
+            ..Default::default()
+        },
+        TransformPlugin,
+        HierarchyPlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<ColorMaterial>()
+    .add_plugins(GameplayPlugin)
+    .insert_resource(Time::<Fixed>::from_duration(timestep))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+    .insert_resource(map)
+    .insert_resource(game_manager)
+    .insert_resource(GameRng::new(seed))
+    .insert_resource(GameAssets::default())
+    .insert_resource(AudioAssets::default())
+    .insert_resource(AudioChannel::<SoundChannel>::default())
+    .insert_resource(AudioChannel::<DrumsChannel>::default())
+    .insert_resource(VolumeSettings::default())
+    .insert_resource(UiData::default())
+    .insert_resource(UiStateResource::default())
+    .insert_resource(Inventory::default())
+    .insert_resource(GameSpeed(false))
+    .add_systems(Update, discard_sounds);
+    app
+}
+
+/// Play through a scenario as fast as possible. Files named in the scenario are read from
+/// `assets_dir`.
+pub fn run(scenario: &Scenario, assets_dir: &Path) -> Result<SimulationReport, SimulationError> {
+    let timestep = Duration::from_secs_f32(scenario.timestep);
+    play(scenario, assets_dir, timestep).map(|(report, ..)| report)
+}
+
+/// Play through a scenario like [`run`], and also return the run as a replay. Towers placed by
+/// the scenario aren't something a player can do, so the scenario can only get towers by taking
+/// the ones offered between waves. The scenario's timestep is ignored, as replays are played at
+/// the game's own tick rate.
+pub fn record(
+    scenario: &Scenario,
+    assets_dir: &Path,
+) -> Result<(SimulationReport, Replay), SimulationError> {
+    if !scenario.towers.is_empty() {
+        return Err(SimulationError::Unrecordable);
+    }
+    let timestep = Time::<Fixed>::from_hz(TICKS_PER_SECOND).timestep();
+    play(scenario, assets_dir, timestep).map(|(report, replay, _)| (report, replay))
+}
+
+/// Play through a scenario, returning the app as it was at the end along with the report and
+/// replay.
+fn play(
+    scenario: &Scenario,
+    assets_dir: &Path,
+    timestep: Duration,
+) -> Result<(SimulationReport, Replay, App), SimulationError> {
+    let (map_data, wave_list) =
+        load_map_and_waves(assets_dir, &scenario.map, scenario.waves.as_deref())?;
+    let map = Map::from_data(&map_data)?;
+
+    // Check the towers can be placed, in order, before starting
+    let mut check_map = Map::from_data(&map_data)?;
+    for tower in scenario.towers.iter() {
+        check_map
+            .place_tower(tower.pos, Entity::PLACEHOLDER)
+            .map_err(|_| SimulationError::InvalidPlacement { pos: tower.pos })?;
+    }
+
+    let seed = scenario.seed.unwrap_or_else(rand::random);
+    let game_manager = GameManager::new(
+        Handle::default(),
+        wave_list.waves.clone(),
+        WaveGenerator::new(wave_list.endless.clone(), seed),
+    );
+    let starting_lives = game_manager.lives;
+
+    let mut app = headless_app(map, game_manager, seed, timestep);
+    app.insert_resource(PendingTowers(scenario.towers.iter().cloned().collect()))
+        .insert_resource(TakeOffers(scenario.take_offers))
+        .init_resource::<Recording>()
+        .add_systems(Update, place_towers)
+        .add_systems(
+            FixedUpdate,
+            (play_turn, record_actions).chain().before(GameplaySet),
+        );
+    app.finish();
+    app.cleanup();
+
+    let max_updates_per_wave = (MAX_SECONDS_PER_WAVE / timestep.as_secs_f32()) as usize;
+    let mut updates = 0;
+    let mut wave = 0;
+    loop {
+        app.update();
+        let game_manager = app.world().resource::<GameManager>();
+        if game_manager.current_wave != wave {
+            wave = game_manager.current_wave;
+            updates = 0;
+        }
+        updates += 1;
+        let cleared = game_manager.current_wave >= scenario.max_waves
+            && matches!(game_manager.wave_state, WaveState::Waiting);
+        let timed_out = updates > max_updates_per_wave;
+        if cleared || timed_out || game_manager.lives == 0 {
+            let report = SimulationReport {
+                seed,
+                wave_reached: game_manager.current_wave,
+                score: game_manager.score,
+                lives_lost: starting_lives - game_manager.lives,
+                timed_out,
+            };
+            let replay = Replay {
+                seed,
+                mode: RunMode::Normal,
+                map: map_data,
+                waves: wave_list,
+                actions: app.world().resource::<Recording>().actions.clone(),
+                ticks: game_manager.tick,
+                score: report.score,
+                wave_reached: report.wave_reached,
+            };
+            return Ok((report, replay, app));
+        }
+    }
+}
+
+/// Play a replay back as fast as possible on the given map and waves, ignoring the ones stored
+/// in the replay.
+pub fn replay(
+    replay: &Replay,
+    map_data: &MapData,
+    wave_list: &WaveList,
+) -> Result<SimulationReport, SimulationError> {
+    let map = Map::from_data(map_data)?;
+    wave_list.validate()?;
+    let game_manager = GameManager::new(
+        Handle::default(),
+        wave_list.waves.clone(),
+        WaveGenerator::new(wave_list.endless.clone(), replay.seed),
+    );
+    let starting_lives = game_manager.lives;
+
+    // Replays are recorded at the game's own tick rate
+    let timestep = Time::<Fixed>::from_hz(TICKS_PER_SECOND).timestep();
+    let mut app = headless_app(map, game_manager, replay.seed, timestep);
+    app.insert_resource(ReplayPlayback::new(replay.clone()))
+        .add_systems(FixedUpdate, play_actions.before(GameplaySet));
+    app.finish();
+    app.cleanup();
+
+    loop {
+        let game_manager = app.world().resource::<GameManager>();
+        let playback = app.world().resource::<ReplayPlayback>();
+        // Once the player has nothing left to do and no wave will start by itself, nothing
+        // more can happen
+        let stalled = playback.next_action >= replay.actions.len()
+            && matches!(game_manager.wave_state, WaveState::Waiting)
+            && game_manager.countdown.is_none()
+            && game_manager.current_wave > 0;
+        if game_manager.tick >= replay.ticks || game_manager.lives == 0 || stalled {
+            return Ok(SimulationReport {
+                seed: replay.seed,
+                wave_reached: game_manager.current_wave,
+                score: game_manager.score,
+                lives_lost: starting_lives - game_manager.lives,
+                timed_out: false,
+            });
+        }
+        app.update();
+    }
+}
+
+/// Place the scenario's towers before the first wave.
+fn place_towers(
+    mut pending: ResMut<PendingTowers>,
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    mut event_writer: EventWriter<TowerPlaced>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map: ResMut<Map>,
+) {
+    for setup in pending.0.drain(..) {
+        let tower = Tower::new(setup.damage, setup.rate, setup.variant, setup.debuff);
+        spawn_tower(
+            tower,
+            &mut commands,
+            setup.pos,
+            &game_assets,
+            &mut event_writer,
+            &mut meshes,
+            &mut materials,
+            &mut map,
+        );
+    }
+}
+
+/// Whether to take the towers offered between waves.
+#[derive(Resource)]
+struct TakeOffers(bool);
+
+/// Play the scenario's turn the way a player would: deal with any towers on offer, then start
+/// the next wave as soon as the last one is cleared.
+fn play_turn(
+    mut commands: Commands,
+    mut ui_state: ResMut<UiStateResource>,
+    options: Query<Entity, With<TowerOptionsRoot>>,
+    take_offers: Res<TakeOffers>,
+    inventory: Res<Inventory>,
+    game_manager: Res<GameManager>,
+    map: Res<Map>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    if let UiState::PickingTower(_) = ui_state.state {
+        if take_offers.0 {
+            actions.send(PlayerAction::PickTower { index: 0 });
+        } else {
+            ui_state.state = UiState::Normal;
+            for entity in options.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+    // The tower just picked only reaches the inventory once the pick is applied, so it's placed
+    // on the next tick
+    if !inventory.towers.is_empty() {
+        let free_tile = (0..map.height as i8)
+            .flat_map(|y| (0..map.width as i8).map(move |x| (x, y)))
+            .find(|pos| map.is_valid_placement(*pos));
+        if let Some(pos) = free_tile {
+            actions.send(PlayerAction::PlaceTower { index: 0, pos });
+        }
+    }
+    if matches!(game_manager.wave_state, WaveState::Waiting) {
+        actions.send(PlayerAction::StartWave);
+    }
+}
+
+/// Nothing plays the queued sounds, so throw them away before they pile up.
+fn discard_sounds(
+    mut sound_channel: ResMut<AudioChannel<SoundChannel>>,
+    mut drums_channel: ResMut<AudioChannel<DrumsChannel>>,
+) {
+    *sound_channel = AudioChannel::default();
+    *drums_channel = AudioChannel::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{replay::RecordedAction, waves::Wave};
+
+    use super::*;
+
+    fn circuit() -> Scenario {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        load_scenario(&root.join("simulations/circuit.sim.ron")).unwrap()
+    }
+
+    fn assets_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    #[test]
+    fn circuit_with_seed_1_loses_on_wave_5_with_50_points() {
+        let report = run(&circuit(), &assets_dir()).unwrap();
+        assert_eq!(report.seed, 1);
+        assert_eq!(report.wave_reached, 5);
+        assert_eq!(report.score, 50);
+        assert_eq!(report.lives_lost, 15);
+        assert!(!report.timed_out);
+    }
+
+    #[test]
+    fn circuit_towers_are_placed_and_deal_damage() {
+        let scenario = circuit();
+        let timestep = Duration::from_secs_f32(scenario.timestep);
+        let (report, _, mut app) = play(&scenario, &assets_dir(), timestep).unwrap();
+        let map = app.world().resource::<Map>();
+        for setup in scenario.towers.iter() {
+            let entity = map.placements[&setup.pos];
+            let tower = app.world().get::<Tower>(entity).unwrap();
+            assert_eq!(tower.variant, setup.variant);
+        }
+        let mut towers = app.world_mut().query::<&Tower>();
+        assert_eq!(towers.iter(app.world()).count(), scenario.towers.len());
+        assert!(report.wave_reached > 1);
+
+        // Only towers kill enemies, so without them nothing scores
+        let unarmed = Scenario {
+            towers: Vec::new(),
+            ..scenario.clone()
+        };
+        let unarmed_report = run(&unarmed, &assets_dir()).unwrap();
+        assert_eq!(unarmed_report.score, 0);
+        assert!(report.score > 0);
+    }
+
+    #[test]
+    fn same_seed_gives_same_report() {
+        let scenario = circuit();
+        let first = run(&scenario, &assets_dir()).unwrap();
+        let second = run(&scenario, &assets_dir()).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn wave_edits_are_replayed() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let scenario = load_scenario(&root.join("simulations/offers.sim.ron")).unwrap();
+        let (report, mut recorded) = record(&scenario, &assets_dir()).unwrap();
+        assert!(report.lives_lost > 0);
+
+        // Swap every wave for an empty one before the first wave starts
+        let empty = WaveList {
+            waves: vec![Wave::default(); 20],
+            ..recorded.waves.clone()
+        };
+        recorded.actions.insert(
+            0,
+            RecordedAction {
+                tick: 0,
+                action: PlayerAction::LoadWaves { waves: empty },
+            },
+        );
+        let edited = replay(&recorded, &recorded.map, &recorded.waves).unwrap();
+        assert_eq!(edited.lives_lost, 0);
+    }
+}
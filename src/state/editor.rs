@@ -0,0 +1,590 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+use bevy_kira_audio::{AudioChannel, AudioControl};
+
+use crate::{
+    audio::{AudioAssets, DrumsChannel, MusicChannel, SoundChannel},
+    gameplay::RunMode,
+    grid::{
+        fit_camera_to_map,
+        map_file::{MapData, MapMode, BUILDABLE_TILE, PATH_TILE},
+        Map, SelectedMap,
+    },
+    ui::constants::*,
+};
+
+use super::{loading::GameAssets, State};
+
+/// The biggest map the editor will make in either direction.
+const MAX_MAP_SIZE: usize = 64;
+/// The smallest map the editor will make in either direction.
+const MIN_MAP_SIZE: usize = 2;
+/// The longest name a map can be given.
+const MAX_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Path,
+    Buildable,
+    Spawn,
+    Exit,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum EditorButton {
+    Tool(Tool),
+    Wider,
+    Narrower,
+    Taller,
+    Shorter,
+    ToggleMode,
+    Name,
+    Play,
+    Load,
+    Export,
+    Menu,
+}
+
+/// The map being edited. Kept around after leaving the editor so that test-playing a map and
+/// coming back doesn't lose any changes.
+#[derive(Resource, Debug)]
+pub struct EditorState {
+    pub map: MapData,
+    tool: Tool,
+    /// Shown instead of the validation result, e.g. after exporting.
+    message: Option<String>,
+    /// Whether the map's name is being typed in.
+    naming: bool,
+    /// The file the map was last loaded from or exported to. Exporting can overwrite this file,
+    /// but not any other.
+    file: Option<String>,
+}
+
+#[derive(Component)]
+struct EditorRoot;
+
+#[derive(Component)]
+struct EditorSprite;
+
+#[derive(Component)]
+struct EditorStatusText;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(State::Editor), setup)
+            .add_systems(Update, button_system.run_if(in_state(State::Editor)))
+            .add_systems(Update, name_input.run_if(in_state(State::Editor)))
+            .add_systems(Update, paint_tiles.run_if(in_state(State::Editor)))
+            .add_systems(Update, draw_map.run_if(in_state(State::Editor)))
+            .add_systems(Update, update_status_text.run_if(in_state(State::Editor)))
+            .add_systems(Update, update_button_colors.run_if(in_state(State::Editor)))
+            .add_systems(OnExit(State::Editor), cleanup);
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    maps: Res<Assets<MapData>>,
+    editor: Option<ResMut<EditorState>>,
+) {
+    match editor {
+        // Make sure the map gets redrawn
+        Some(mut editor) => editor.set_changed(),
+        None => {
+            let mut map = maps
+                .get(&game_assets.map)
+                .expect("Map asset should be loaded before opening the editor")
+                .clone();
+            map.name = "Custom Map".to_string();
+            commands.insert_resource(EditorState {
+                map,
+                tool: Tool::Path,
+                message: None,
+                naming: false,
+                file: None,
+            });
+        }
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::SpaceBetween,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(EditorRoot)
+        .with_children(|parent| {
+            // Toolbar
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Auto,
+                        flex_wrap: FlexWrap::Wrap,
+                        padding: UiRect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    background_color: CARD_BACKGROUND_COLOR.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    let font = game_assets.font.clone();
+                    add_button(parent, "Path", EditorButton::Tool(Tool::Path), &font);
+                    add_button(
+                        parent,
+                        "Buildable",
+                        EditorButton::Tool(Tool::Buildable),
+                        &font,
+                    );
+                    add_button(parent, "Spawn", EditorButton::Tool(Tool::Spawn), &font);
+                    add_button(parent, "Exit", EditorButton::Tool(Tool::Exit), &font);
+                    add_button(parent, "Width +", EditorButton::Wider, &font);
+                    add_button(parent, "Width -", EditorButton::Narrower, &font);
+                    add_button(parent, "Height +", EditorButton::Taller, &font);
+                    add_button(parent, "Height -", EditorButton::Shorter, &font);
+                    add_button(parent, "Mode", EditorButton::ToggleMode, &font);
+                    add_button(parent, "Name", EditorButton::Name, &font);
+                    add_button(parent, "Play", EditorButton::Play, &font);
+                    add_button(parent, "Load", EditorButton::Load, &font);
+                    add_button(parent, "Export", EditorButton::Export, &font);
+                    add_button(parent, "Menu", EditorButton::Menu, &font);
+                });
+            // Status text
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Px(50.0),
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    background_color: CARD_BACKGROUND_COLOR.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: game_assets.font.clone(),
+                                font_size: 20.0,
+                                color: TEXT_COLOR,
+                            },
+                        ))
+                        .insert(EditorStatusText);
+                });
+        });
+}
+
+fn add_button(parent: &mut ChildBuilder, text: &str, button: EditorButton, font: &Handle<Font>) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(100.0),
+                height: Val::Px(40.0),
+                margin: UiRect::all(Val::Px(5.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: BUTTON_BACKGROUND_COLOR.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 20.0,
+                    color: BUTTON_TEXT_COLOR,
+                },
+            ));
+        })
+        .insert(button);
+}
+
+fn button_system(
+    mut commands: Commands,
+    interaction_query: Query<(&EditorButton, &Interaction), (Changed<Interaction>, With<Button>)>,
+    mut editor: ResMut<EditorState>,
+    mut maps: ResMut<Assets<MapData>>,
+    mut next_state: ResMut<NextState<State>>,
+    music_channel: Res<AudioChannel<MusicChannel>>,
+    drums_channel: Res<AudioChannel<DrumsChannel>>,
+    sound_channel: Res<AudioChannel<SoundChannel>>,
+    audio_assets: Res<AudioAssets>,
+) {
+    for (button, interaction) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Pressed => {
+                sound_channel.play(audio_assets.blip2.clone());
+                let editor = &mut *editor;
+                editor.message = None;
+                // Clicking anywhere finishes typing the name
+                let was_naming = std::mem::take(&mut editor.naming);
+                let map = &mut editor.map;
+                let width = map.tiles[0].len();
+                let height = map.tiles.len();
+                match button {
+                    EditorButton::Tool(tool) => editor.tool = *tool,
+                    EditorButton::Wider => {
+                        if width < MAX_MAP_SIZE {
+                            map.tiles
+                                .iter_mut()
+                                .for_each(|row| row.push(BUILDABLE_TILE));
+                        }
+                    }
+                    EditorButton::Narrower => {
+                        if width > MIN_MAP_SIZE {
+                            map.tiles.iter_mut().for_each(|row| {
+                                row.pop();
+                            });
+                        }
+                    }
+                    EditorButton::Taller => {
+                        if height < MAX_MAP_SIZE {
+                            map.tiles.push(vec![BUILDABLE_TILE; width]);
+                        }
+                    }
+                    EditorButton::Shorter => {
+                        if height > MIN_MAP_SIZE {
+                            map.tiles.pop();
+                        }
+                    }
+                    EditorButton::ToggleMode => {
+                        map.mode = match map.mode {
+                            MapMode::Path => MapMode::Maze,
+                            MapMode::Maze => MapMode::Path,
+                        };
+                    }
+                    EditorButton::Name => editor.naming = !was_naming,
+                    EditorButton::Play => match map.validate() {
+                        Ok(()) => {
+                            let handle = maps.add(map.clone());
+                            commands.insert_resource(SelectedMap(handle));
+                            commands.insert_resource(RunMode::Normal);
+                            next_state.set(State::Game);
+                        }
+                        Err(e) => editor.message = Some(format!("Can't play: {}", e)),
+                    },
+                    EditorButton::Load => {
+                        let path = map_path(&map.name);
+                        editor.message = Some(match load(&path) {
+                            Ok(loaded) => {
+                                *map = loaded;
+                                editor.file = Some(path.clone());
+                                format!("Loaded {}", path)
+                            }
+                            Err(e) => format!("Load failed: {}", e),
+                        });
+                    }
+                    EditorButton::Export => {
+                        let path = map_path(&map.name);
+                        editor.message = Some(match export(map, &path, &editor.file) {
+                            Ok(path) => {
+                                editor.file = Some(path.clone());
+                                format!("Exported to {}", path)
+                            }
+                            Err(e) => format!("Export failed: {}", e),
+                        });
+                    }
+                    EditorButton::Menu => {
+                        // The main menu starts the music again
+                        music_channel.stop();
+                        drums_channel.stop();
+                        next_state.set(State::MainMenu);
+                    }
+                }
+                // Drop any spawns and exits that are no longer next to the map
+                let map = &mut editor.map;
+                let (width, height) = (map.tiles[0].len() as i8, map.tiles.len() as i8);
+                let near_map =
+                    |(x, y): &(i8, i8)| *x >= -1 && *y >= -1 && *x <= width && *y <= height;
+                map.spawns.retain(near_map);
+                map.exits.retain(near_map);
+            }
+            Interaction::Hovered => {
+                sound_channel.play(audio_assets.blip1.clone());
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+fn update_button_colors(
+    mut query: Query<(&EditorButton, &Interaction, &mut BackgroundColor)>,
+    editor: Res<EditorState>,
+) {
+    for (button, interaction, mut background_color) in query.iter_mut() {
+        background_color.0 = match (interaction, button) {
+            (Interaction::Pressed, _) => BUTTON_BACKGROUND_COLOR_PRESSED,
+            (Interaction::Hovered, _) => BUTTON_BACKGROUND_COLOR_HOVER,
+            (Interaction::None, EditorButton::Tool(tool)) if *tool == editor.tool => {
+                BUTTON_BACKGROUND_COLOR_ACTIVE
+            }
+            (Interaction::None, _) => BUTTON_BACKGROUND_COLOR,
+        };
+    }
+}
+
+/// Where a map with the given name is kept in `assets/maps`.
+fn map_path(name: &str) -> String {
+    let file_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("assets/maps/{}.map.ron", file_name)
+}
+
+/// Load a map from `assets/maps` to carry on editing it. It doesn't have to be a valid map yet,
+/// but its tiles have to be a grid the editor can work with.
+#[cfg(not(target_arch = "wasm32"))]
+fn load(path: &str) -> Result<MapData, String> {
+    let map: MapData =
+        crate::storage::load_file(std::path::Path::new(path)).map_err(|e| e.to_string())?;
+    map.validate_shape().map_err(|e| e.to_string())?;
+    Ok(map)
+}
+
+/// The browser can't read the game's assets, so there's nothing to load.
+#[cfg(target_arch = "wasm32")]
+fn load(_path: &str) -> Result<MapData, String> {
+    Err("maps can only be loaded in the desktop build".to_string())
+}
+
+/// Write the map to `assets/maps` so it can be loaded by the game. Only the file the map came
+/// from can be overwritten, so exporting a renamed map can't replace some other map.
+#[cfg(not(target_arch = "wasm32"))]
+fn export(map: &MapData, path: &str, file: &Option<String>) -> Result<String, String> {
+    map.validate().map_err(|e| e.to_string())?;
+    if std::path::Path::new(path).exists() && file.as_deref() != Some(path) {
+        return Err(format!(
+            "{} already exists, give the map another name",
+            path
+        ));
+    }
+    // Keep each row of tiles on a single line
+    let config = ron::ser::PrettyConfig::new().depth_limit(2);
+    let contents = ron::ser::to_string_pretty(map, config).map_err(|e| e.to_string())?;
+    std::fs::write(path, contents).map_err(|e| e.to_string())?;
+    Ok(path.to_string())
+}
+
+/// The browser can't write to the game's assets, so log the map for it to be copied out instead.
+#[cfg(target_arch = "wasm32")]
+fn export(map: &MapData, _path: &str, _file: &Option<String>) -> Result<String, String> {
+    map.validate().map_err(|e| e.to_string())?;
+    let config = ron::ser::PrettyConfig::new().depth_limit(2);
+    let contents = ron::ser::to_string_pretty(map, config).map_err(|e| e.to_string())?;
+    info!("{}", contents);
+    Ok("the browser console".to_string())
+}
+
+/// Type in the map's name, which is also the name of the file it's loaded from and exported to.
+fn name_input(mut events: EventReader<KeyboardInput>, mut editor: ResMut<EditorState>) {
+    for event in events.read() {
+        if !editor.naming || event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(c)
+                if c.chars().all(|c| !c.is_control())
+                    && editor.map.name.chars().count() + c.chars().count() <= MAX_NAME_LENGTH =>
+            {
+                editor.map.name.push_str(c);
+            }
+            Key::Space if editor.map.name.chars().count() < MAX_NAME_LENGTH => {
+                editor.map.name.push(' ');
+            }
+            Key::Backspace => {
+                editor.map.name.pop();
+            }
+            Key::Enter | Key::Escape => editor.naming = false,
+            _ => {}
+        }
+    }
+}
+
+fn paint_tiles(
+    mut editor: ResMut<EditorState>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    interactions: Query<&Interaction>,
+) {
+    if !mouse_input.pressed(MouseButton::Left)
+        || interactions.iter().any(|i| *i != Interaction::None)
+    {
+        return;
+    }
+    let Some(mouse_pos) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let (camera, camera_transform) = camera.get_single().unwrap();
+    let Some(world_position) = camera.viewport_to_world(camera_transform, mouse_pos) else {
+        return;
+    };
+    let (x, y) = Map::get_grid_pos(world_position.origin.truncate());
+    let width = editor.map.tiles[0].len() as i8;
+    let height = editor.map.tiles.len() as i8;
+    let in_bounds = x >= 0 && y >= 0 && x < width && y < height;
+    // Spawns and exits can also go just outside the map
+    let near_map = x >= -1 && y >= -1 && x <= width && y <= height;
+    match editor.tool {
+        Tool::Path | Tool::Buildable if in_bounds => {
+            let tile = if editor.tool == Tool::Path {
+                PATH_TILE
+            } else {
+                BUILDABLE_TILE
+            };
+            if editor.map.tiles[y as usize][x as usize] != tile {
+                editor.map.tiles[y as usize][x as usize] = tile;
+                editor.message = None;
+            }
+        }
+        Tool::Spawn | Tool::Exit if near_map && mouse_input.just_pressed(MouseButton::Left) => {
+            let editor = &mut *editor;
+            let positions = if editor.tool == Tool::Spawn {
+                &mut editor.map.spawns
+            } else {
+                &mut editor.map.exits
+            };
+            if let Some(i) = positions.iter().position(|p| *p == (x, y)) {
+                positions.remove(i);
+            } else {
+                positions.push((x, y));
+            }
+            editor.message = None;
+        }
+        _ => {}
+    }
+}
+
+fn draw_map(
+    mut commands: Commands,
+    editor: Res<EditorState>,
+    sprites: Query<Entity, With<EditorSprite>>,
+    mut cameras: Query<(&mut OrthographicProjection, &mut Transform)>,
+    game_assets: Res<GameAssets>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    for entity in sprites.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let map = &editor.map;
+    for (y, row) in map.tiles.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            commands
+                .spawn(SpriteBundle {
+                    texture: if *tile == PATH_TILE {
+                        game_assets.empty_tile.clone()
+                    } else {
+                        game_assets.buildable_tile.clone()
+                    },
+                    transform: Transform::from_translation(Vec3::new(
+                        x as f32 * 32.0,
+                        y as f32 * 32.0,
+                        0.0,
+                    )),
+                    ..Default::default()
+                })
+                .insert(EditorSprite);
+        }
+    }
+    let markers = map
+        .spawns
+        .iter()
+        .map(|pos| (pos, GREEN))
+        .chain(map.exits.iter().map(|pos| (pos, RED)));
+    for ((x, y), color) in markers {
+        commands
+            .spawn(SpriteBundle {
+                texture: game_assets.tile_select.clone(),
+                sprite: Sprite {
+                    color,
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(Vec3::new(
+                    *x as f32 * 32.0,
+                    *y as f32 * 32.0,
+                    1.0,
+                )),
+                ..Default::default()
+            })
+            .insert(EditorSprite);
+    }
+    for (mut projection, mut transform) in cameras.iter_mut() {
+        fit_camera_to_map(
+            map.tiles[0].len() as u8,
+            map.tiles.len() as u8,
+            &mut projection,
+            &mut transform,
+        );
+    }
+}
+
+fn update_status_text(
+    mut query: Query<&mut Text, With<EditorStatusText>>,
+    editor: Res<EditorState>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = query.get_single_mut() {
+        let map = &editor.map;
+        let mode = match map.mode {
+            MapMode::Path => "Path",
+            MapMode::Maze => "Maze",
+        };
+        let status = match &editor.message {
+            Some(message) => message.clone(),
+            None => match map.validate() {
+                Ok(()) => "Map is valid".to_string(),
+                Err(e) => format!("Invalid map: {}", e),
+            },
+        };
+        if editor.naming {
+            text.sections[0].value = format!("Name: {}_", map.name);
+            return;
+        }
+        text.sections[0].value = format!(
+            "{}: {}x{} {} map. {}",
+            map.name,
+            map.tiles[0].len(),
+            map.tiles.len(),
+            mode,
+            status
+        );
+    }
+}
+
+fn cleanup(
+    mut commands: Commands,
+    root: Query<Entity, With<EditorRoot>>,
+    sprites: Query<Entity, With<EditorSprite>>,
+) {
+    for entity in root.iter().chain(sprites.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,169 +1,244 @@
 use bevy::prelude::*;
 
+use super::loading::GameAssets;
 use crate::{
-    enemies, gameplay, grid, input,
-    tower::{self, debuffs::SpeedUpPoint},
-    ui::{self, inventory, sidebar, statusbar, tower_options},
+    actions::PlayerAction,
+    daily,
+    gameplay::{self, GameplayPlugin, GameplaySet, RunMode},
+    grid::{self, map_file::MapData, SelectedMap},
+    input,
+    replay::{self, Recording, ReplayPlayback},
+    rng::{GameRng, SelectedSeed},
+    save::{self, ResumeRun},
+    settings::SpeedPreference,
+    tower::debuffs::SpeedUpPoint,
+    ui::{
+        self, inventory,
+        overlay::{self, SideEffectOverlay},
+        sidebar, statusbar, tower_options, wave_preview,
+    },
+    waves::{self, WaveGenerator, WaveList},
 };
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<tower::TowerPlaced>()
-            .add_event::<tower::debuffs::AddDebuff>()
-            .add_systems(OnEnter(super::State::Game), setup)
-            .add_systems(OnEnter(super::State::Game), grid::load_map)
-            .add_systems(OnEnter(super::State::Game), statusbar::draw_status_bar)
-            .add_systems(
-                Update,
-                gameplay::gameloop.run_if(in_state(super::State::Game)),
-            ) // gameplay::gameloop.in_set(OnUpdate(super::State::Game)))
-            .add_systems(
-                Update,
-                gameplay::start_next_wave.run_if(in_state(super::State::Game)),
+        app.init_resource::<RunMode>()
+            .add_plugins(GameplayPlugin)
+            .configure_sets(
+                FixedUpdate,
+                GameplaySet.run_if(in_state(super::State::Game).and_then(replay::replay_ticking)),
             )
             .add_systems(
-                Update,
-                gameplay::game_over_check.run_if(in_state(super::State::Game)),
+                FixedUpdate,
+                replay::record_actions
+                    .before(GameplaySet)
+                    .run_if(in_state(super::State::Game).and_then(resource_exists::<Recording>)),
             )
             .add_systems(
-                Update,
-                (
-                    enemies::enemy_movement.run_if(in_state(super::State::Game)),
-                    enemies::update_enemy_grid_pos.run_if(in_state(super::State::Game)),
-                )
-                    .chain(),
+                FixedUpdate,
+                replay::play_actions.before(GameplaySet).run_if(
+                    in_state(super::State::Game)
+                        .and_then(resource_exists::<ReplayPlayback>)
+                        .and_then(replay::replay_ticking),
+                ),
             )
             .add_systems(
-                Update,
-                (
-                    enemies::check_killed.run_if(in_state(super::State::Game)),
-                    enemies::update_healthbar.run_if(in_state(super::State::Game)),
-                    enemies::scale_healthbar.run_if(in_state(super::State::Game)),
-                )
-                    .chain(),
+                FixedUpdate,
+                save::autosave
+                    .after(GameplaySet)
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
+            .add_systems(OnEnter(super::State::Game), setup)
+            .add_systems(OnEnter(super::State::Game), grid::load_map)
             .add_systems(
-                Update,
-                tower::handle_tower_placement.run_if(in_state(super::State::Game)),
+                OnEnter(super::State::Game),
+                start_at_preferred_speed
+                    .after(setup)
+                    .run_if(not(watching_replay)),
             )
             .add_systems(
-                Update,
-                tower::debuffs::debuff_event_handler.run_if(in_state(super::State::Game)),
+                OnEnter(super::State::Game),
+                save::resume_run.after(setup).after(grid::load_map),
             )
+            .add_systems(OnEnter(super::State::Game), statusbar::draw_status_bar)
             .add_systems(
                 Update,
-                tower::debuffs::handle_overheat.run_if(in_state(super::State::Game)),
+                waves::reload_waves.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                tower::charge_shot::shoot.run_if(in_state(super::State::Game)),
+                (gameplay::game_over_check, save::discard_finished_run)
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                tower::sniper::shoot.run_if(in_state(super::State::Game)),
+                ui::update_selection_indicator.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                tower::handle_projectiles.run_if(in_state(super::State::Game)),
+                inventory::draw_inventory.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                tower::laser::shoot.run_if(in_state(super::State::Game)),
+                inventory::handle_inventory_buttons
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                tower::jammer::rotate_dish.run_if(in_state(super::State::Game)),
+                inventory::create_ghost.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                tower::missile::spawn_missile.run_if(in_state(super::State::Game)),
+                inventory::handle_ghost.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                tower::missile::handle_missile.run_if(in_state(super::State::Game)),
+                overlay::draw_side_effect_overlay
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                ui::update_selection_indicator.run_if(in_state(super::State::Game)),
+                tower_options::handle_tower_options
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                inventory::draw_inventory.run_if(in_state(super::State::Game)),
+                sidebar::draw_sidebar.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                inventory::handle_inventory_buttons.run_if(in_state(super::State::Game)),
+                wave_preview::draw_wave_preview.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                inventory::create_ghost.run_if(in_state(super::State::Game)),
+                sidebar::handle_toggle_rotation_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                inventory::handle_ghost.run_if(in_state(super::State::Game)),
+                sidebar::handle_upgrade_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                tower_options::handle_tower_options.run_if(in_state(super::State::Game)),
+                sidebar::handle_sell_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                sidebar::draw_sidebar.run_if(in_state(super::State::Game)),
+                statusbar::update_status_bar_text.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                sidebar::handle_toggle_rotation_button.run_if(in_state(super::State::Game)),
+                statusbar::handle_auto_start_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                statusbar::update_status_bar_text.run_if(in_state(super::State::Game)),
+                statusbar::update_score_text.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                statusbar::update_score_text.run_if(in_state(super::State::Game)),
+                statusbar::update_lives_text.run_if(in_state(super::State::Game)),
             )
             .add_systems(
                 Update,
-                statusbar::update_lives_text.run_if(in_state(super::State::Game)),
+                statusbar::handle_normal_speed_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                statusbar::handle_normal_speed_button.run_if(in_state(super::State::Game)),
+                statusbar::handle_fast_speed_button
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                statusbar::handle_fast_speed_button.run_if(in_state(super::State::Game)),
+                input::grid_click_handler
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
-                input::grid_click_handler.run_if(in_state(super::State::Game)),
+                input::start_wave_key
+                    .run_if(in_state(super::State::Game).and_then(not(watching_replay))),
             )
             .add_systems(
                 Update,
                 input::mouse_hover_handler.run_if(in_state(super::State::Game)),
             )
+            .add_systems(OnExit(super::State::Game), replay::finish_recording)
             .add_systems(OnExit(super::State::Game), cleanup);
     }
 }
 
+/// Whether the game being shown is a replay, so the player's input should be ignored.
+fn watching_replay(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_some()
+}
+
 fn setup(
     mut commands: Commands,
-    mut cameras: Query<(&mut OrthographicProjection, &mut Transform)>,
+    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
+    selected_map: Res<SelectedMap>,
+    maps: Res<Assets<MapData>>,
+    mut wave_lists: ResMut<Assets<WaveList>>,
+    selected_seed: Res<SelectedSeed>,
+    run_mode: Res<RunMode>,
+    playback: Option<Res<ReplayPlayback>>,
+    resume: Option<Res<ResumeRun>>,
+    mut time: ResMut<Time<Virtual>>,
 ) {
+    let seed = match (&playback, &resume, *run_mode) {
+        (Some(playback), _, _) => playback.replay.seed,
+        (None, Some(resume), _) => resume.0.seed,
+        (None, None, RunMode::Normal) => selected_seed.0.unwrap_or_else(rand::random),
+        (None, None, RunMode::Daily(day)) => daily::seed(day),
+    };
+    info!("Seed: {}", seed);
+
+    // Replays and saved runs bring their own waves. Otherwise use the map's own waves if it has
+    // any. If they haven't loaded yet they're picked up by `waves::reload_waves` once they have.
+    let wave_source = match (
+        &playback,
+        &resume,
+        maps.get(&selected_map.0).and_then(|map| map.waves.as_ref()),
+    ) {
+        (Some(playback), _, _) => wave_lists.add(playback.replay.waves.clone()),
+        (None, Some(resume), _) => wave_lists.add(resume.0.waves.clone()),
+        (None, None, Some(path)) => asset_server.load(path.clone()),
+        (None, None, None) => game_assets.waves.clone(),
+    };
+    let (waves, endless) = wave_lists
+        .get(&wave_source)
+        .map(|list| (list.waves.clone(), list.endless.clone()))
+        .unwrap_or_default();
+    let generator = WaveGenerator::new(endless, seed);
+
     // Insert resources
-    commands.insert_resource(gameplay::GameManager::new());
+    commands.insert_resource(gameplay::GameManager::new(wave_source, waves, generator));
+    commands.insert_resource(GameRng::new(seed));
     commands.insert_resource(ui::UiData::default());
     commands.insert_resource(ui::UiStateResource::default());
     commands.insert_resource(input::HoverPosition::default());
     commands.insert_resource(inventory::Inventory::default());
     commands.insert_resource(ui::statusbar::GameSpeed(false));
-    // Position the camera
-    for (mut projection, mut transform) in cameras.iter_mut() {
-        projection.scale = 0.4;
-        transform.translation.x = 76.0;
-        transform.translation.y = 76.0;
+    time.set_relative_speed(1.0);
+    // A saved run carries on with its own recording, if it has one
+    if playback.is_none() && resume.is_none() {
+        commands.insert_resource(Recording::default());
+    }
+}
+
+/// Start at fast speed if that's how the player last left it. This goes through an action like
+/// pressing the button does, so replays of the run speed up at the same point.
+fn start_at_preferred_speed(
+    speed_preference: Res<SpeedPreference>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    if speed_preference.0 {
+        actions.send(PlayerAction::SetSpeed { fast: true });
     }
 }
 
@@ -172,11 +247,13 @@ fn cleanup(
     mut sprites: Query<Entity, With<Sprite>>,
     mut nodes: Query<Entity, With<Node>>,
     mut speed_up_points: Query<Entity, With<SpeedUpPoint>>,
+    mut overlays: Query<Entity, With<SideEffectOverlay>>,
 ) {
     for entity in sprites
         .iter_mut()
         .chain(nodes.iter_mut())
         .chain(speed_up_points.iter_mut())
+        .chain(overlays.iter_mut())
     {
         commands.entity(entity).despawn_recursive();
     }
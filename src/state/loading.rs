@@ -1,9 +1,11 @@
 use bevy::{core_pipeline::bloom::BloomSettings, prelude::*};
 use bevy_asset_loader::prelude::AssetCollection;
 
-use crate::ui::constants::TEXT_COLOR;
+use crate::{
+    enemies::EnemyVariant, grid::map_file::MapData, ui::constants::TEXT_COLOR, waves::WaveList,
+};
 
-#[derive(AssetCollection, Resource)]
+#[derive(AssetCollection, Resource, Default)]
 pub struct GameAssets {
     #[asset(path = "fonts/roboto.ttf")]
     pub font: Handle<Font>,
@@ -47,6 +49,25 @@ pub struct GameAssets {
     pub missile: Handle<Image>,
     #[asset(path = "tiles/overheat.png")]
     pub overheat: Handle<Image>,
+    #[asset(path = "maps/default.map.ron")]
+    pub map: Handle<MapData>,
+    #[asset(path = "maps/maze.map.ron")]
+    pub maze_map: Handle<MapData>,
+    #[asset(path = "waves/default.waves.ron")]
+    pub waves: Handle<WaveList>,
+}
+impl GameAssets {
+    pub fn enemy_texture(&self, variant: EnemyVariant) -> Handle<Image> {
+        match variant {
+            EnemyVariant::Weak => self.enemy1.clone(),
+            EnemyVariant::Normal => self.enemy2.clone(),
+            EnemyVariant::Fast => self.enemy3.clone(),
+            EnemyVariant::Strong => self.enemy4.clone(),
+            EnemyVariant::Boss => self.enemy5.clone(),
+            EnemyVariant::StrongFast => self.enemy6.clone(),
+            EnemyVariant::UltraBoss => self.enemy7.clone(),
+        }
+    }
 }
 
 #[derive(Component)]
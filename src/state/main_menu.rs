@@ -1,10 +1,22 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
 use bevy_kira_audio::{AudioChannel, AudioControl, AudioTween};
 
 use crate::{
     audio::{AudioAssets, DrumsChannel, MusicChannel, SoundChannel, VolumeSettings},
+    daily,
+    gameplay::RunMode,
+    grid::{map_file::MapData, SelectedMap},
+    replay::{self, LastReplay},
+    rng::SelectedSeed,
+    save::{self, ResumeRun},
     ui::constants::*,
 };
 
@@ -15,17 +27,43 @@ struct MainMenuRoot;
 
 #[derive(Component)]
 enum MenuButton {
+    Continue,
     Start,
+    Maze,
+    Daily,
+    Replay,
+    Editor,
+    Seed,
     Sound,
     Music,
 }
 
+/// The seed being typed in on the main menu.
+#[derive(Resource, Default)]
+struct SeedInput {
+    editing: bool,
+    text: String,
+}
+impl SeedInput {
+    /// Stop editing and use the typed seed for the next run. An empty seed means a random one.
+    fn commit(&mut self, selected_seed: &mut SelectedSeed) {
+        self.editing = false;
+        selected_seed.0 = self.text.parse().ok();
+        if selected_seed.0.is_none() {
+            self.text.clear();
+        }
+    }
+}
+
 pub struct MainMenuPlugin;
 
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(State::MainMenu), setup)
+        app.init_resource::<SelectedSeed>()
+            .add_systems(OnEnter(State::MainMenu), setup)
             .add_systems(Update, button_system.run_if(in_state(State::MainMenu)))
+            .add_systems(Update, seed_input.run_if(in_state(State::MainMenu)))
+            .add_systems(Update, update_seed_text.run_if(in_state(State::MainMenu)))
             .add_systems(
                 Update,
                 update_button_volume_text.run_if(in_state(State::MainMenu)),
@@ -40,7 +78,15 @@ fn setup(
     audio_assets: Res<AudioAssets>,
     music_channel: Res<AudioChannel<MusicChannel>>,
     drums_channel: Res<AudioChannel<DrumsChannel>>,
+    selected_seed: Res<SelectedSeed>,
 ) {
+    commands.insert_resource(SeedInput {
+        editing: false,
+        text: selected_seed
+            .0
+            .map(|seed| seed.to_string())
+            .unwrap_or_default(),
+    });
     music_channel
         .play(audio_assets.bgm_main.clone())
         .looped()
@@ -78,7 +124,30 @@ fn setup(
                 },
                 ..Default::default()
             });
+            if save::has_save() {
+                add_button(
+                    parent,
+                    "Continue",
+                    MenuButton::Continue,
+                    game_assets.font.clone(),
+                );
+            }
             add_button(parent, "Start", MenuButton::Start, game_assets.font.clone());
+            add_button(parent, "Maze", MenuButton::Maze, game_assets.font.clone());
+            add_button(parent, "Daily", MenuButton::Daily, game_assets.font.clone());
+            add_button(
+                parent,
+                "Replay",
+                MenuButton::Replay,
+                game_assets.font.clone(),
+            );
+            add_button(
+                parent,
+                "Editor",
+                MenuButton::Editor,
+                game_assets.font.clone(),
+            );
+            add_button(parent, "Seed", MenuButton::Seed, game_assets.font.clone());
             add_button(parent, "Sound", MenuButton::Sound, game_assets.font.clone());
             add_button(parent, "Music", MenuButton::Music, game_assets.font.clone());
         });
@@ -116,21 +185,74 @@ fn add_button(parent: &mut ChildBuilder, text: &str, button: MenuButton, font: H
 
 fn button_system(
     mut interaction_query: Query<
-        (&MenuButton, &Interaction, &mut BackgroundColor),
+        (Entity, &MenuButton, &Interaction, &mut BackgroundColor),
         (Changed<Interaction>, With<Button>),
     >,
+    mut commands: Commands,
     mut next_state: ResMut<NextState<State>>,
     mut volume_settings: ResMut<VolumeSettings>,
+    mut seed_input: ResMut<SeedInput>,
+    mut selected_seed: ResMut<SelectedSeed>,
+    game_assets: Res<GameAssets>,
+    mut maps: ResMut<Assets<MapData>>,
+    last_replay: Res<LastReplay>,
     music_channel: Res<AudioChannel<MusicChannel>>,
     sound_channel: Res<AudioChannel<SoundChannel>>,
     audio_assets: Res<AudioAssets>,
 ) {
-    for (button, interaction, mut color) in &mut interaction_query {
+    for (entity, button, interaction, mut color) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 *color = BUTTON_BACKGROUND_COLOR_PRESSED.into();
+                if seed_input.editing {
+                    // Clicking anywhere finishes typing the seed
+                    seed_input.commit(&mut selected_seed);
+                    if let MenuButton::Seed = button {
+                        continue;
+                    }
+                }
                 match button {
-                    MenuButton::Start => next_state.set(State::Game),
+                    MenuButton::Continue => {
+                        // Pick up the run saved between waves. A save that can't be read has
+                        // been thrown away, so there's nothing left to continue.
+                        match save::load_saved() {
+                            Some(run) => {
+                                commands.insert_resource(SelectedMap(maps.add(run.map.clone())));
+                                commands.insert_resource(run.mode);
+                                commands.insert_resource(ResumeRun(run));
+                                next_state.set(State::Game);
+                            }
+                            None => commands.entity(entity).despawn_recursive(),
+                        }
+                    }
+                    MenuButton::Start => {
+                        commands.insert_resource(SelectedMap(game_assets.map.clone()));
+                        commands.insert_resource(RunMode::Normal);
+                        next_state.set(State::Game);
+                    }
+                    MenuButton::Maze => {
+                        commands.insert_resource(SelectedMap(game_assets.maze_map.clone()));
+                        commands.insert_resource(RunMode::Normal);
+                        next_state.set(State::Game);
+                    }
+                    MenuButton::Daily => {
+                        // Everyone plays the same map on the daily challenge
+                        commands.insert_resource(SelectedMap(game_assets.map.clone()));
+                        commands.insert_resource(RunMode::Daily(daily::today()));
+                        next_state.set(State::Game);
+                    }
+                    MenuButton::Replay => {
+                        // Watch the last run played
+                        if let Some(replay) = replay::latest(&last_replay) {
+                            super::replay::watch(&mut commands, &mut maps, replay);
+                            next_state.set(State::Game);
+                        }
+                    }
+                    MenuButton::Editor => next_state.set(State::Editor),
+                    MenuButton::Seed => {
+                        seed_input.editing = true;
+                        sound_channel.play(audio_assets.blip2.clone());
+                    }
                     MenuButton::Sound => {
                         volume_settings.toggle_sfx_vol();
                         sound_channel.set_volume(volume_settings.sfx_vol);
@@ -178,6 +300,53 @@ fn update_button_volume_text(
     }
 }
 
+fn seed_input(
+    mut events: EventReader<KeyboardInput>,
+    mut seed_input: ResMut<SeedInput>,
+    mut selected_seed: ResMut<SelectedSeed>,
+) {
+    for event in events.read() {
+        if !seed_input.editing || event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(c) if c.chars().all(|c| c.is_ascii_digit()) => {
+                // Seeds are u64s, which have at most 20 digits
+                if seed_input.text.len() + c.len() <= 20 {
+                    seed_input.text.push_str(c);
+                }
+            }
+            Key::Backspace => {
+                seed_input.text.pop();
+            }
+            Key::Enter | Key::Escape => seed_input.commit(&mut selected_seed),
+            _ => {}
+        }
+    }
+}
+
+fn update_seed_text(
+    query: Query<(&MenuButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+    seed_input: Res<SeedInput>,
+) {
+    if !seed_input.is_changed() {
+        return;
+    }
+    for (button, children) in query.iter() {
+        if let MenuButton::Seed = button {
+            let mut text = text_query.get_mut(children[0]).unwrap();
+            text.sections[0].value = if seed_input.editing {
+                format!("Seed: {}_", seed_input.text)
+            } else if seed_input.text.is_empty() {
+                "Seed: Random".to_string()
+            } else {
+                format!("Seed: {}", seed_input.text)
+            };
+        }
+    }
+}
+
 fn cleanup(mut commands: Commands, root: Query<Entity, With<MainMenuRoot>>) {
     for entity in root.iter() {
         commands.entity(entity).despawn_recursive();
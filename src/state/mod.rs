@@ -1,8 +1,10 @@
 use bevy::prelude::States;
 
+pub mod editor;
 pub mod game;
 pub mod loading;
 pub mod main_menu;
+pub mod replay;
 pub mod results;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
@@ -12,4 +14,7 @@ pub enum State {
     MainMenu,
     Game,
     Results,
+    Editor,
+    /// Restarting a replay, on the way back into `Game`.
+    Replay,
 }
@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    gameplay::{GameManager, TICKS_PER_SECOND},
+    grid::{map_file::MapData, SelectedMap},
+    replay::{Replay, ReplayPlayback},
+    ui::{constants::*, statusbar::GameSpeed},
+};
+
+use super::{loading::GameAssets, State};
+
+/// How far the skip buttons and arrow keys jump, in seconds.
+const SKIP_SECONDS: f64 = 10.0;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 16.0;
+/// How much game time can pass in one frame while watching a replay. This is raised from the
+/// default so that fast-forwarding isn't held back by the frame rate.
+const REPLAY_MAX_DELTA: Duration = Duration::from_secs(2);
+const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(250);
+
+#[derive(Component)]
+struct ReplayControlsRoot;
+
+#[derive(Component, Clone, Copy)]
+enum ReplayButton {
+    Pause,
+    Back,
+    Slower,
+    Faster,
+    Forward,
+    Menu,
+}
+
+#[derive(Component)]
+struct ReplayProgressBar;
+
+#[derive(Component)]
+struct ReplayProgressFill;
+
+#[derive(Component)]
+struct ReplayStatusText;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(State::Replay), restart)
+            .add_systems(
+                OnEnter(State::Game),
+                draw_replay_controls.run_if(resource_exists::<ReplayPlayback>),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_replay_buttons,
+                    handle_progress_bar,
+                    replay_keys,
+                    update_playback_time,
+                    update_replay_controls,
+                )
+                    .chain()
+                    .run_if(in_state(State::Game).and_then(resource_exists::<ReplayPlayback>)),
+            );
+    }
+}
+
+/// Start watching a replay from the beginning.
+pub fn watch(commands: &mut Commands, maps: &mut Assets<MapData>, replay: Replay) {
+    commands.insert_resource(SelectedMap(maps.add(replay.map.clone())));
+    commands.insert_resource(replay.mode);
+    commands.insert_resource(ReplayPlayback::new(replay));
+}
+
+/// Jump to a tick in the replay. Going backwards means playing the run again from the start.
+fn seek(
+    playback: &mut ReplayPlayback,
+    current_tick: u64,
+    target: u64,
+    next_state: &mut NextState<State>,
+) {
+    let target = target.min(playback.replay.ticks);
+    if target < current_tick {
+        playback.next_action = 0;
+        next_state.set(State::Replay);
+    }
+    playback.seek = Some(target);
+}
+
+fn skip(
+    playback: &mut ReplayPlayback,
+    current_tick: u64,
+    seconds: f64,
+    next_state: &mut NextState<State>,
+) {
+    let target = (current_tick as f64 + seconds * TICKS_PER_SECOND).max(0.0) as u64;
+    seek(playback, current_tick, target, next_state);
+}
+
+fn exit_replay(
+    commands: &mut Commands,
+    time: &mut Time<Virtual>,
+    next_state: &mut NextState<State>,
+) {
+    commands.remove_resource::<ReplayPlayback>();
+    time.set_relative_speed(1.0);
+    time.set_max_delta(DEFAULT_MAX_DELTA);
+    next_state.set(State::MainMenu);
+}
+
+/// Passing through this state restarts the game, so a replay can be played again from the
+/// start.
+fn restart(mut next_state: ResMut<NextState<State>>) {
+    next_state.set(State::Game);
+}
+
+fn draw_replay_controls(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let text_style = TextStyle {
+        font: game_assets.font.clone(),
+        font_size: 20.0,
+        color: BUTTON_TEXT_COLOR,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                height: Val::Px(50.0),
+                position_type: PositionType::Absolute,
+                left: Val::Px(0.0),
+                top: Val::Px(50.0),
+                align_items: AlignItems::Center,
+                padding: UiRect::horizontal(Val::Px(10.0)),
+                column_gap: Val::Px(10.0),
+                ..Default::default()
+            },
+            background_color: CARD_BACKGROUND_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(ReplayControlsRoot)
+        .with_children(|parent| {
+            for (label, button) in [
+                ("Pause", ReplayButton::Pause),
+                ("-10s", ReplayButton::Back),
+                ("Slower", ReplayButton::Slower),
+                ("Faster", ReplayButton::Faster),
+                ("+10s", ReplayButton::Forward),
+                ("Menu", ReplayButton::Menu),
+            ] {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(70.0),
+                            height: Val::Px(36.0),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        background_color: BUTTON_BACKGROUND_COLOR.into(),
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                    });
+            }
+            // Clicking the progress bar jumps to that point in the run
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(12.0),
+                        ..Default::default()
+                    },
+                    background_color: BUTTON_BACKGROUND_COLOR.into(),
+                    ..Default::default()
+                })
+                .insert((ReplayProgressBar, RelativeCursorPosition::default()))
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..Default::default()
+                            },
+                            background_color: GREEN.into(),
+                            ..Default::default()
+                        })
+                        .insert(ReplayProgressFill);
+                });
+            parent
+                .spawn(TextBundle::from_section("", text_style))
+                .insert(ReplayStatusText);
+        });
+}
+
+fn handle_replay_buttons(
+    mut commands: Commands,
+    mut query: Query<(&ReplayButton, &Interaction, &mut BackgroundColor), Changed<Interaction>>,
+    mut playback: ResMut<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    mut time: ResMut<Time<Virtual>>,
+    mut next_state: ResMut<NextState<State>>,
+) {
+    for (button, interaction, mut background_color) in query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR_PRESSED;
+                match button {
+                    ReplayButton::Pause => playback.paused = !playback.paused,
+                    ReplayButton::Back => skip(
+                        &mut playback,
+                        game_manager.tick,
+                        -SKIP_SECONDS,
+                        &mut next_state,
+                    ),
+                    ReplayButton::Slower => {
+                        playback.speed = (playback.speed / 2.0).max(MIN_SPEED);
+                    }
+                    ReplayButton::Faster => {
+                        playback.speed = (playback.speed * 2.0).min(MAX_SPEED);
+                    }
+                    ReplayButton::Forward => skip(
+                        &mut playback,
+                        game_manager.tick,
+                        SKIP_SECONDS,
+                        &mut next_state,
+                    ),
+                    ReplayButton::Menu => exit_replay(&mut commands, &mut time, &mut next_state),
+                }
+            }
+            Interaction::Hovered => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR_HOVER;
+            }
+            Interaction::None => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR;
+            }
+        }
+    }
+}
+
+fn handle_progress_bar(
+    query: Query<
+        (&Interaction, &RelativeCursorPosition),
+        (With<ReplayProgressBar>, Changed<Interaction>),
+    >,
+    mut playback: ResMut<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    mut next_state: ResMut<NextState<State>>,
+) {
+    for (interaction, cursor) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(position) = cursor.normalized else {
+            continue;
+        };
+        let target = (position.x.clamp(0.0, 1.0) as f64 * playback.replay.ticks as f64) as u64;
+        seek(&mut playback, game_manager.tick, target, &mut next_state);
+    }
+}
+
+fn replay_keys(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    mut time: ResMut<Time<Virtual>>,
+    mut next_state: ResMut<NextState<State>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        skip(
+            &mut playback,
+            game_manager.tick,
+            -SKIP_SECONDS,
+            &mut next_state,
+        );
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        skip(
+            &mut playback,
+            game_manager.tick,
+            SKIP_SECONDS,
+            &mut next_state,
+        );
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        playback.speed = (playback.speed / 2.0).max(MIN_SPEED);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        playback.speed = (playback.speed * 2.0).min(MAX_SPEED);
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit_replay(&mut commands, &mut time, &mut next_state);
+    }
+}
+
+/// Run the game at the speed the viewer asked for, and stop fast-forwarding once the target
+/// has been reached.
+fn update_playback_time(
+    mut playback: ResMut<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    game_speed: Res<GameSpeed>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if let Some(target) = playback.seek {
+        if game_manager.tick >= target || playback.finished(game_manager.tick) {
+            playback.seek = None;
+        }
+    }
+    time.set_max_delta(REPLAY_MAX_DELTA);
+    time.set_relative_speed(playback.time_speed(game_speed.0));
+}
+
+fn update_replay_controls(
+    playback: Res<ReplayPlayback>,
+    game_manager: Res<GameManager>,
+    mut fill: Query<&mut Style, With<ReplayProgressFill>>,
+    mut status: Query<&mut Text, With<ReplayStatusText>>,
+    buttons: Query<(&ReplayButton, &Children)>,
+    mut labels: Query<&mut Text, Without<ReplayStatusText>>,
+) {
+    let tick = game_manager.tick;
+    let ticks = playback.replay.ticks.max(1);
+    if let Ok(mut style) = fill.get_single_mut() {
+        style.width = Val::Percent(tick.min(ticks) as f32 / ticks as f32 * 100.0);
+    }
+    if let Ok(mut text) = status.get_single_mut() {
+        let state = if playback.finished(tick) {
+            "Replay finished".to_string()
+        } else if playback.seek.is_some() {
+            "Seeking...".to_string()
+        } else {
+            format!("{}x", playback.speed)
+        };
+        text.sections[0].value = format!(
+            "{} / {}  {}",
+            format_time(tick),
+            format_time(playback.replay.ticks),
+            state
+        );
+    }
+    for (button, children) in buttons.iter() {
+        if let ReplayButton::Pause = button {
+            for child in children.iter() {
+                if let Ok(mut text) = labels.get_mut(*child) {
+                    text.sections[0].value =
+                        if playback.paused { "Play" } else { "Pause" }.to_string();
+                }
+            }
+        }
+    }
+}
+
+fn format_time(tick: u64) -> String {
+    let seconds = (tick as f64 / TICKS_PER_SECOND) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -1,9 +1,19 @@
 use bevy::prelude::*;
-use bevy_jornet::{JornetPlugin, Leaderboard, Score};
+use bevy_jornet::{JornetPlugin, Leaderboard};
 use bevy_kira_audio::{AudioChannel, AudioControl};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::leaderboard::http::HttpLeaderboard;
 use crate::{
     audio::{AudioAssets, DrumsChannel, MusicChannel, SoundChannel},
+    gameplay::RunMode,
+    leaderboard::{
+        self, loadout,
+        local::{self, LocalLeaderboard},
+        score_category, LeaderboardBackend, LeaderboardConfig, RunScore,
+    },
+    replay::LastReplay,
+    tower::TowerType,
     ui::constants::*,
 };
 
@@ -14,40 +24,86 @@ pub struct ResultsPlugin;
 
 impl Plugin for ResultsPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Scores::default());
-        if let Some(id) = option_env!("JORNET_ID") {
-            if let Some(key) = option_env!("JORNET_KEY") {
-                app.add_plugins(JornetPlugin::with_leaderboard(id, key))
-                    .add_systems(OnEnter(State::Results), save_score)
-                    .add_systems(Update, draw_leaderboard.run_if(in_state(State::Results)))
-                    .add_systems(
-                        Update,
-                        handle_main_menu_button.run_if(in_state(State::Results)),
-                    )
-                    .add_systems(Update, refresh_after_timer.run_if(in_state(State::Results)))
-                    .add_systems(OnExit(State::Results), cleanup);
+        app.init_resource::<Scores>();
+        match LeaderboardConfig::from_env() {
+            LeaderboardConfig::Jornet { id, key } => {
+                app.add_plugins(JornetPlugin::with_leaderboard(id, key));
+                add_leaderboard_systems::<Leaderboard>(app);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            LeaderboardConfig::Http { url } => {
+                info!("Sending scores to {}", url);
+                app.insert_resource(HttpLeaderboard::new(url));
+                add_leaderboard_systems::<HttpLeaderboard>(app);
+            }
+            LeaderboardConfig::Local => {
+                app.insert_resource(local::load());
+                add_leaderboard_systems::<LocalLeaderboard>(app);
             }
         }
+        app.add_systems(
+            Update,
+            handle_main_menu_button.run_if(in_state(State::Results)),
+        )
+        .add_systems(OnExit(State::Results), cleanup);
     }
 }
 
+fn add_leaderboard_systems<B: LeaderboardBackend>(app: &mut App) {
+    app.add_systems(Startup, leaderboard::create_player::<B>)
+        .add_systems(Update, leaderboard::poll_leaderboard::<B>)
+        .add_systems(OnEnter(State::Results), save_score::<B>)
+        .add_systems(
+            Update,
+            (refresh_after_timer::<B>, draw_leaderboard::<B>)
+                .chain()
+                .after(leaderboard::poll_leaderboard::<B>)
+                .run_if(in_state(State::Results)),
+        );
+}
+
 #[derive(Resource, Debug, Default)]
 pub struct Scores {
     pub high_score: u32,
     pub last_score: u32,
     pub last_wave: u32,
+    pub last_seed: u64,
+    pub last_mode: RunMode,
+    /// The towers on the map when the last run ended.
+    pub last_towers: Vec<TowerType>,
+    /// Whether the last run was recorded from the start. Runs picked up from saves made before
+    /// the random number generator's state was kept can't be replayed, so they aren't sent.
+    pub last_recorded: bool,
 }
 
-pub fn create_player(mut leaderboard: ResMut<Leaderboard>) {
-    leaderboard.create_player(None);
+impl Scores {
+    /// The result of the last run played.
+    fn last_run(&self) -> RunScore {
+        RunScore {
+            score: self.last_score,
+            wave: self.last_wave,
+            seed: self.last_seed,
+            mode: self.last_mode,
+            towers: self.last_towers.clone(),
+            replay: None,
+        }
+    }
 }
 
-fn save_score(mut commands: Commands, leaderboard: Res<Leaderboard>, scores: Res<Scores>) {
-    leaderboard.send_score_with_meta(
-        scores.last_score as f32,
-        &format!("Wave {}", scores.last_wave),
-    );
-    leaderboard.refresh_leaderboard();
+fn save_score<B: LeaderboardBackend>(
+    mut commands: Commands,
+    mut backend: ResMut<B>,
+    scores: Res<Scores>,
+    last_replay: Res<LastReplay>,
+) {
+    if scores.last_recorded {
+        let run = RunScore {
+            replay: last_replay.0.clone(),
+            ..scores.last_run()
+        };
+        backend.send_score(&run);
+    }
+    backend.refresh_leaderboard();
     commands.insert_resource(RefreshTimer(Timer::from_seconds(3.0, TimerMode::Once)));
 }
 
@@ -56,19 +112,84 @@ pub struct LeaderboardRoot;
 #[derive(Component)]
 pub struct MainMenuButton;
 
-fn draw_leaderboard(
+fn draw_leaderboard<B: LeaderboardBackend>(
     mut commands: Commands,
-    leaderboard: Res<Leaderboard>,
+    backend: Res<B>,
     query: Query<Entity, With<LeaderboardRoot>>,
     game_assets: Res<GameAssets>,
     score: Res<Scores>,
 ) {
-    if !leaderboard.is_changed() {
+    if !backend.is_changed() {
         return;
     }
     if let Some(entity) = query.iter().next() {
         commands.entity(entity).despawn_recursive();
     }
+    let run = score.last_run();
+    let category = score_category(run.mode);
+    let entries = backend.get_leaderboard(&category);
+    let mut lines = Vec::new();
+    if let Some(name) = backend.player_name() {
+        lines.push(format!("You are: {}", name));
+    }
+    lines.push(format!(
+        "Your score: {} (High score: {})",
+        score.last_score, score.high_score
+    ));
+    lines.push(format!("Your towers: {}", loadout(&score.last_towers)));
+    lines.push(format!("Seed: {}", score.last_seed));
+    if !score.last_recorded {
+        lines.push("Resumed from an old save, so this run isn't on the leaderboard".to_string());
+    }
+    draw_leaderboard_screen(
+        &mut commands,
+        game_assets.font.clone(),
+        LeaderboardScreen {
+            title: leaderboard_title(&category),
+            rank: score
+                .last_recorded
+                .then(|| backend.rank(&run))
+                .flatten()
+                .map(|rank| (rank, entries.len())),
+            rows: entries
+                .into_iter()
+                .take(10)
+                .map(|entry| LeaderboardRow {
+                    name: entry.player,
+                    score: format!("{}", entry.score),
+                    meta: entry.meta,
+                })
+                .collect(),
+            lines,
+        },
+    );
+}
+
+fn leaderboard_title(category: &Option<String>) -> String {
+    match category {
+        Some(category) => format!("{} Leaderboard", category),
+        None => "Leaderboard".to_string(),
+    }
+}
+
+/// One row of a leaderboard, as the text in each of its columns.
+struct LeaderboardRow {
+    name: String,
+    score: String,
+    meta: String,
+}
+
+/// Everything shown on the results screen, whichever leaderboard it comes from.
+struct LeaderboardScreen {
+    title: String,
+    /// Where the run just played ranks, and out of how many.
+    rank: Option<(usize, usize)>,
+    rows: Vec<LeaderboardRow>,
+    /// Lines of text shown below the leaderboard.
+    lines: Vec<String>,
+}
+
+fn draw_leaderboard_screen(commands: &mut Commands, font: Handle<Font>, screen: LeaderboardScreen) {
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -99,9 +220,9 @@ fn draw_leaderboard(
                 .with_children(|parent| {
                     parent.spawn(TextBundle {
                         text: Text::from_section(
-                            "Leaderboard",
+                            screen.title,
                             TextStyle {
-                                font: game_assets.font.clone(),
+                                font: font.clone(),
                                 font_size: 50.0,
                                 color: TEXT_COLOR,
                             },
@@ -121,104 +242,64 @@ fn draw_leaderboard(
                     ..Default::default()
                 })
                 .with_children(|parent| {
-                    let mut scores = leaderboard.get_leaderboard();
-                    let count = scores.len();
-                    scores.sort_unstable_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-                    if let Some(player) = leaderboard.get_player() {
-                        if let Some(rank) = scores.iter().position(|s| {
-                            s.player == player.name && s.score == score.last_score as f32
-                        }) {
-                            parent
-                                .spawn(NodeBundle {
-                                    style: Style {
-                                        width: Val::Percent(100.0),
-                                        height: Val::Px(70.0),
+                    if let Some((rank, count)) = screen.rank {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(100.0),
+                                    height: Val::Px(70.0),
 
-                                        justify_content: JustifyContent::Center,
-                                        align_items: AlignItems::Center,
-                                        ..Default::default()
-                                    },
-                                    background_color: CARD_BACKGROUND_COLOR_HOVER.into(),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: CARD_BACKGROUND_COLOR_HOVER.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle {
+                                    text: Text::from_section(
+                                        format!("You are ranked {} out of {}", rank + 1, count),
+                                        TextStyle {
+                                            font: font.clone(),
+                                            font_size: 30.0,
+                                            color: TEXT_COLOR,
+                                        },
+                                    ),
                                     ..Default::default()
-                                })
-                                .with_children(|parent| {
-                                    parent.spawn(TextBundle {
-                                        text: Text::from_section(
-                                            format!("You are ranked {} out of {}", rank + 1, count),
-                                            TextStyle {
-                                                font: game_assets.font.clone(),
-                                                font_size: 30.0,
-                                                color: TEXT_COLOR,
-                                            },
-                                        ),
-                                        ..Default::default()
-                                    });
                                 });
-                        }
+                            });
                     }
-                    scores.truncate(10);
-                    for score in scores {
-                        draw_leaderboard_row(parent, game_assets.font.clone(), score);
+                    for row in screen.rows {
+                        draw_leaderboard_row(parent, font.clone(), row);
                     }
                 });
-            // Display player name
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.0),
-                        height: Val::Px(50.0),
-
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    let name = match leaderboard.get_player() {
-                        Some(player) => player.name.clone(),
-                        None => "Error".to_string(),
-                    };
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            format!("You are: {}", name),
-                            TextStyle {
-                                font: game_assets.font.clone(),
-                                font_size: 30.0,
-                                color: TEXT_COLOR,
-                            },
-                        ),
-                        ..Default::default()
-                    });
-                });
-            // Display player score
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.0),
-                        height: Val::Px(50.0),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
+            for line in screen.lines {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
                         ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            format!(
-                                "Your score: {} (High score: {})",
-                                score.last_score, score.high_score
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                line,
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: TEXT_COLOR,
+                                },
                             ),
-                            TextStyle {
-                                font: game_assets.font.clone(),
-                                font_size: 30.0,
-                                color: TEXT_COLOR,
-                            },
-                        ),
-                        ..Default::default()
+                            ..Default::default()
+                        });
                     });
-                });
+            }
             // Main menu button
             parent
                 .spawn(ButtonBundle {
@@ -237,7 +318,7 @@ fn draw_leaderboard(
                         text: Text::from_section(
                             "Main Menu",
                             TextStyle {
-                                font: game_assets.font.clone(),
+                                font: font.clone(),
                                 font_size: 30.0,
                                 color: TEXT_COLOR,
                             },
@@ -248,100 +329,61 @@ fn draw_leaderboard(
         });
 }
 
-fn draw_leaderboard_row(parent: &mut ChildBuilder, font: Handle<Font>, score: Score) {
+fn draw_leaderboard_row(parent: &mut ChildBuilder, font: Handle<Font>, row: LeaderboardRow) {
     parent
         .spawn(NodeBundle {
             style: Style {
                 width: Val::Percent(100.0),
-                height: Val::Px(40.0),
+                min_height: Val::Px(40.0),
                 ..Default::default()
             },
             ..Default::default()
         })
         .with_children(|parent| {
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(33.3),
-                        height: Val::Px(40.0),
-
-                        justify_content: JustifyContent::Center,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            score.player,
-                            TextStyle {
-                                font: font.clone(),
-                                font_size: 25.0,
-                                color: TEXT_COLOR,
-                            },
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(33.3),
-                        height: Val::Px(40.0),
-                        justify_content: JustifyContent::Center,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            format!("{}", score.score),
-                            TextStyle {
-                                font: font.clone(),
-                                font_size: 25.0,
-                                color: TEXT_COLOR,
-                            },
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(33.3),
-                        height: Val::Px(40.0),
-                        justify_content: JustifyContent::Center,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            score.meta.unwrap_or("".to_string()),
-                            TextStyle {
-                                font: font.clone(),
-                                font_size: 25.0,
-                                color: TEXT_COLOR,
-                            },
-                        ),
+            // The meta column can hold a run's whole tower loadout, so it gets the most room
+            let columns = [
+                (row.name, 35.0, 25.0),
+                (row.score, 15.0, 25.0),
+                (row.meta, 50.0, 20.0),
+            ];
+            for (column, width, font_size) in columns {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(width),
+                            min_height: Val::Px(40.0),
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
                         ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                column,
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: TEXT_COLOR,
+                                },
+                            ),
+                            ..Default::default()
+                        });
                     });
-                });
+            }
         });
 }
 
 #[derive(Resource)]
 pub struct RefreshTimer(pub Timer);
 
-fn refresh_after_timer(
-    leaderboard: Res<Leaderboard>,
+fn refresh_after_timer<B: LeaderboardBackend>(
+    mut backend: ResMut<B>,
     mut timer: ResMut<RefreshTimer>,
     time: Res<Time>,
 ) {
     if timer.0.tick(time.delta()).just_finished() {
-        leaderboard.refresh_leaderboard();
+        backend.refresh_leaderboard();
     }
 }
 
@@ -0,0 +1,178 @@
+//! Files kept between sessions: settings, the saved run, replays and the local leaderboard.
+//! Natively they live in the platform's config directory, and in the browser in local storage,
+//! keyed by the same relative path.
+
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("there's nowhere to keep {name}")]
+    Unavailable { name: String },
+    #[error("could not access {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("could not store {name}")]
+    Refused { name: String },
+    #[error("could not parse {name}: {source}")]
+    Parse {
+        name: String,
+        source: ron::error::SpannedError,
+    },
+    #[error("could not write {name}: {source}")]
+    Serialize { name: String, source: ron::Error },
+}
+
+/// Read a stored RON file, or `None` if it hasn't been stored yet.
+pub fn load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, StorageError> {
+    read(name)?
+        .map(|contents| parse(name, &contents))
+        .transpose()
+}
+
+/// Store a value as a RON file, replacing any stored before.
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), StorageError> {
+    let contents =
+        ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()).map_err(|source| {
+            StorageError::Serialize {
+                name: name.to_string(),
+                source,
+            }
+        })?;
+    write(name, &contents)
+}
+
+/// Read a RON file from anywhere on disk, like a replay passed on the command line.
+pub fn load_file<T: DeserializeOwned>(path: &Path) -> Result<T, StorageError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| StorageError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(&path.display().to_string(), &contents)
+}
+
+fn parse<T: DeserializeOwned>(name: &str, contents: &str) -> Result<T, StorageError> {
+    ron::from_str(contents).map_err(|source| StorageError::Parse {
+        name: name.to_string(),
+        source,
+    })
+}
+
+/// The directory everything is kept in, following each platform's convention.
+#[cfg(not(target_arch = "wasm32"))]
+fn config_dir() -> Option<PathBuf> {
+    use std::env;
+
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(name: &str) -> Result<PathBuf, StorageError> {
+    config_dir()
+        .map(|dir| dir.join(name))
+        .ok_or_else(|| StorageError::Unavailable {
+            name: name.to_string(),
+        })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read(name: &str) -> Result<Option<String>, StorageError> {
+    let path = path(name)?;
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(StorageError::Io { path, source }),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(name: &str, contents: &str) -> Result<(), StorageError> {
+    let path = path(name)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|source| StorageError::Io {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+    }
+    std::fs::write(&path, contents).map_err(|source| StorageError::Io { path, source })
+}
+
+/// Whether anything is stored under the name.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn exists(name: &str) -> bool {
+    path(name).is_ok_and(|path| path.exists())
+}
+
+/// Delete whatever is stored under the name, if anything.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(name: &str) -> Result<(), StorageError> {
+    let path = path(name)?;
+    match std::fs::remove_file(&path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(StorageError::Io {
+            path,
+            source: error,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage(name: &str) -> Result<web_sys::Storage, StorageError> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok()?)
+        .ok_or_else(|| StorageError::Unavailable {
+            name: name.to_string(),
+        })
+}
+
+/// Local storage is shared with anything else on the same origin, so keys are prefixed.
+#[cfg(target_arch = "wasm32")]
+fn key(name: &str) -> String {
+    format!("{}/{}", env!("CARGO_PKG_NAME"), name)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read(name: &str) -> Result<Option<String>, StorageError> {
+    local_storage(name)?
+        .get_item(&key(name))
+        .map_err(|_| StorageError::Unavailable {
+            name: name.to_string(),
+        })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(name: &str, contents: &str) -> Result<(), StorageError> {
+    local_storage(name)?
+        .set_item(&key(name), contents)
+        .map_err(|_| StorageError::Refused {
+            name: name.to_string(),
+        })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn exists(name: &str) -> bool {
+    matches!(read(name), Ok(Some(_)))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(name: &str) -> Result<(), StorageError> {
+    local_storage(name)?
+        .remove_item(&key(name))
+        .map_err(|_| StorageError::Refused {
+            name: name.to_string(),
+        })
+}
@@ -0,0 +1,105 @@
+use rand::{distributions::Standard, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    debuffs::{EffectArea, ModifierKind},
+    Tower,
+};
+
+/// The chance of a random tower also having a buff.
+pub const BUFF_CHANCE: f64 = 0.5;
+
+/// A side effect that helps the towers around it, rolled alongside a tower's debuff.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Buff {
+    BoostNeighbourDamage(f32),
+    BoostNeighbourRate(f32),
+    BoostRowRate(f32),
+    BoostColumnDamage(f32),
+    /// Enemies within 1 tile move this many percent slower.
+    SlowAura(f32),
+    /// Turrets and snipers directly next to this tower reach this many tiles further.
+    ExtendNeighbourRange(f32),
+}
+impl Buff {
+    pub fn description(&self) -> String {
+        match self {
+            Buff::BoostNeighbourDamage(percent) => {
+                format!(
+                    "Towers directly next to this one do {}% more damage",
+                    percent
+                )
+            }
+            Buff::BoostNeighbourRate(percent) => {
+                format!("Towers directly next to this one shoot {}% faster", percent)
+            }
+            Buff::BoostRowRate(percent) => {
+                format!("Towers in the same row shoot {}% faster", percent)
+            }
+            Buff::BoostColumnDamage(percent) => {
+                format!("Towers in the same column do {}% more damage", percent)
+            }
+            Buff::SlowAura(percent) => {
+                format!("Enemies within 1 tile move {}% slower", percent)
+            }
+            Buff::ExtendNeighbourRange(tiles) => {
+                format!(
+                    "Turrets and snipers directly next to this one reach {} tiles further",
+                    tiles
+                )
+            }
+        }
+    }
+
+    /// Where the buff takes effect.
+    pub fn area(&self) -> EffectArea {
+        match self {
+            Buff::BoostNeighbourDamage(_)
+            | Buff::BoostNeighbourRate(_)
+            | Buff::ExtendNeighbourRange(_) => EffectArea::Neighbours,
+            Buff::BoostRowRate(_) => EffectArea::Row,
+            Buff::BoostColumnDamage(_) => EffectArea::Column,
+            Buff::SlowAura(_) => EffectArea::Itself,
+        }
+    }
+
+    /// What this buff, on a tower at `source_pos`, does to the `target` tower at `target_pos`.
+    /// The slow aura works on enemies rather than towers, so it's set up when the tower is
+    /// spawned.
+    pub fn modifier_on(
+        &self,
+        source_pos: (i8, i8),
+        target: &Tower,
+        target_pos: (i8, i8),
+    ) -> Option<(ModifierKind, f32)> {
+        if !self.area().contains(source_pos, target_pos) {
+            return None;
+        }
+        match *self {
+            Buff::BoostNeighbourDamage(percent) | Buff::BoostColumnDamage(percent) => {
+                Some((ModifierKind::Damage, percent))
+            }
+            Buff::BoostNeighbourRate(percent) | Buff::BoostRowRate(percent) => {
+                Some((ModifierKind::Rate, percent))
+            }
+            Buff::ExtendNeighbourRange(tiles) => target
+                .range()
+                .is_some()
+                .then_some((ModifierKind::Range, tiles)),
+            Buff::SlowAura(_) => None,
+        }
+    }
+}
+impl Distribution<Buff> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Buff {
+        match rng.gen_range(0..=8) {
+            0..=1 => Buff::BoostNeighbourDamage((rng.gen_range(10.0..=25.0) as f32).round()),
+            2..=3 => Buff::BoostNeighbourRate((rng.gen_range(10.0..=25.0) as f32).round()),
+            4 => Buff::BoostRowRate((rng.gen_range(5.0..=15.0) as f32).round()),
+            5 => Buff::BoostColumnDamage((rng.gen_range(5.0..=15.0) as f32).round()),
+            6..=7 => Buff::SlowAura((rng.gen_range(10.0..=25.0) as f32).round()),
+            8 => Buff::ExtendNeighbourRange(0.5),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,19 +1,21 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use bevy::prelude::*;
 use bevy_kira_audio::{AudioChannel, AudioControl};
 use rand::{distributions::Standard, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     audio::{AudioAssets, SoundChannel},
     gameplay::{GameManager, WaveState},
     grid::Map,
+    rng::GameRng,
     state::loading::GameAssets,
 };
 
-use super::{TargetMode, Tower, TowerType};
+use super::{buffs::Buff, TargetMode, Tower, TowerType};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Debuff {
     MoveSpeedUp(f32),
     ReduceNeighbourDamage(f32),
@@ -96,6 +98,23 @@ impl Debuff {
         }
     }
 }
+impl Debuff {
+    /// How a tower with this debuff picks its targets, if the debuff changes that.
+    pub fn target_mode(&self) -> Option<TargetMode> {
+        match self {
+            Debuff::TargetClosest => Some(TargetMode::Closest),
+            Debuff::TargetRandom => Some(TargetMode::Random),
+            _ => None,
+        }
+    }
+
+    /// Whether a tower can't have both debuffs: they are the same kind, or both change how
+    /// it picks its targets.
+    pub fn conflicts_with(&self, other: &Debuff) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            || (self.target_mode().is_some() && other.target_mode().is_some())
+    }
+}
 impl Distribution<Debuff> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Debuff {
         match rng.gen_range(0..=29) {
@@ -121,68 +140,217 @@ impl Distribution<Debuff> for Standard {
     }
 }
 
+/// How close enemies have to be to a speed up point to be affected by it, in pixels.
+pub const SPEED_UP_RADIUS: f32 = 48.0;
+
 #[derive(Component)]
 pub struct SpeedUpPoint(pub f32);
 
-#[derive(Debug, Event)]
-pub struct AddDebuff {
-    pub grid_pos: (i8, i8),
-    pub debuff: Debuff,
+/// What a modifier changes about a tower.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModifierKind {
+    Damage,
+    Rate,
+    /// Extra range, in tiles rather than percent.
+    Range,
+    /// Lets the tower randomly overheat.
+    Overheat,
+}
+
+/// An effect a tower's debuff has on a tower, which may be itself.
+#[derive(Debug, Clone)]
+pub struct Modifier {
+    /// The tower whose debuff this comes from.
+    pub source: Entity,
+    pub kind: ModifierKind,
+    /// How much the stat changes by, in percent for damage and rate. Unused for overheating.
+    pub magnitude: f32,
+}
+impl Modifier {
+    pub fn description(&self) -> String {
+        match self.kind {
+            ModifierKind::Damage => format!("{:+}% damage", self.magnitude),
+            ModifierKind::Rate => format!("{:+}% rate", self.magnitude),
+            ModifierKind::Range => format!("{:+} range", self.magnitude),
+            ModifierKind::Overheat => "can overheat".to_string(),
+        }
+    }
+}
+
+/// The tiles a debuff reaches, relative to the tower it's on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectArea {
+    Itself,
+    /// The four tiles directly next to the tower.
+    Neighbours,
+    /// Every other tile in the tower's row.
+    Row,
+    /// Every other tile in the tower's column.
+    Column,
+}
+impl EffectArea {
+    pub fn contains(&self, source_pos: (i8, i8), target_pos: (i8, i8)) -> bool {
+        let (dx, dy) = (target_pos.0 - source_pos.0, target_pos.1 - source_pos.1);
+        match self {
+            EffectArea::Itself => dx == 0 && dy == 0,
+            EffectArea::Neighbours => dx.abs() + dy.abs() == 1,
+            EffectArea::Row => dy == 0 && dx != 0,
+            EffectArea::Column => dx == 0 && dy != 0,
+        }
+    }
+}
+
+impl Debuff {
+    /// Where the debuff takes effect. `None` if it doesn't do anything to any tower.
+    pub fn area(&self) -> Option<EffectArea> {
+        match self {
+            Debuff::MoveSpeedUp(_)
+            | Debuff::Overheat
+            | Debuff::TargetClosest
+            | Debuff::TargetRandom => Some(EffectArea::Itself),
+            Debuff::ReduceNeighbourDamage(_)
+            | Debuff::ReduceNeighbourRate(_)
+            | Debuff::TurretIncompatible
+            | Debuff::SniperIncompatible
+            | Debuff::LaserIncompatible
+            | Debuff::MissileIncompatible => Some(EffectArea::Neighbours),
+            Debuff::ReduceRowDamage(_) | Debuff::ReduceRowRate(_) | Debuff::RowOverheat => {
+                Some(EffectArea::Row)
+            }
+            Debuff::ReduceColumnDamage(_)
+            | Debuff::ReduceColumnRate(_)
+            | Debuff::ColumnOverheat => Some(EffectArea::Column),
+            Debuff::Immune => None,
+        }
+    }
+
+    /// What this debuff, on a tower at `source_pos`, does to the `target` tower at
+    /// `target_pos`. Effects that only ever apply to the tower itself and never change, like
+    /// targeting, are set up when the tower is made instead.
+    pub fn modifier_on(
+        &self,
+        source_pos: (i8, i8),
+        target: &Tower,
+        target_pos: (i8, i8),
+    ) -> Option<(ModifierKind, f32)> {
+        if !self.area()?.contains(source_pos, target_pos) {
+            return None;
+        }
+        let incompatible =
+            |variant| (target.variant == variant).then_some((ModifierKind::Damage, -50.0));
+        match *self {
+            Debuff::Overheat | Debuff::RowOverheat | Debuff::ColumnOverheat => {
+                Some((ModifierKind::Overheat, 0.0))
+            }
+            Debuff::ReduceNeighbourDamage(percent)
+            | Debuff::ReduceRowDamage(percent)
+            | Debuff::ReduceColumnDamage(percent) => Some((ModifierKind::Damage, -percent)),
+            Debuff::ReduceNeighbourRate(percent)
+            | Debuff::ReduceRowRate(percent)
+            | Debuff::ReduceColumnRate(percent) => Some((ModifierKind::Rate, -percent)),
+            Debuff::TurretIncompatible => incompatible(TowerType::ChargeShot),
+            Debuff::SniperIncompatible => incompatible(TowerType::Sniper),
+            Debuff::LaserIncompatible => incompatible(TowerType::Laser),
+            Debuff::MissileIncompatible => incompatible(TowerType::Missile),
+            _ => None,
+        }
+    }
+}
+
+/// A tower whose side effects may reach other towers.
+#[derive(Debug, Clone)]
+pub struct SideEffectSource {
+    pub pos: (i8, i8),
+    pub entity: Entity,
+    pub debuffs: Vec<Debuff>,
+    pub buff: Option<Buff>,
+}
+impl SideEffectSource {
+    pub fn new(pos: (i8, i8), entity: Entity, tower: &Tower) -> Self {
+        Self {
+            pos,
+            entity,
+            debuffs: tower.debuffs().cloned().collect(),
+            buff: tower.buff.clone(),
+        }
+    }
+}
+
+/// The modifiers a tower at `pos` gets from the given towers, which should include itself.
+pub fn modifiers_at(tower: &Tower, pos: (i8, i8), sources: &[SideEffectSource]) -> Vec<Modifier> {
+    // Immune towers don't get buffs from the towers around them either
+    let immune = tower.is_immune();
+    sources
+        .iter()
+        .filter(|source| !immune || source.pos == pos)
+        .flat_map(|source| {
+            let debuffs = source
+                .debuffs
+                .iter()
+                .filter_map(|debuff| debuff.modifier_on(source.pos, tower, pos));
+            let buff = source
+                .buff
+                .as_ref()
+                .and_then(|buff| buff.modifier_on(source.pos, tower, pos));
+            debuffs.chain(buff).map(|(kind, magnitude)| Modifier {
+                source: source.entity,
+                kind,
+                magnitude,
+            })
+        })
+        .collect()
 }
 
-pub fn debuff_event_handler(
+/// Work out every tower's modifiers again whenever towers are placed or removed, so a tower's
+/// stats only depend on the towers around it and not on the order they were placed in.
+pub fn update_modifiers(
     mut commands: Commands,
-    mut events: EventReader<AddDebuff>,
-    mut query: Query<&mut Tower>,
+    mut towers: Query<(&mut Tower, Option<&Overheatable>, Option<&Children>)>,
+    overheat_icons: Query<(), With<OverheatIcon>>,
     map: Res<Map>,
+    mut placements: Local<HashMap<(i8, i8), Entity>>,
 ) {
-    for event in events.read() {
-        if let Some(entity) = map.placements.get(&event.grid_pos) {
-            let mut tower = query.get_mut(*entity).expect("Tower entity not found");
-            if let Debuff::Immune = tower.debuff {
-                continue;
-            }
-            match &event.debuff {
-                Debuff::ReduceNeighbourDamage(percent) => tower.reduce_damage_by(*percent),
-                Debuff::ReduceNeighbourRate(percent) => tower.reduce_rate_by(*percent),
-                Debuff::MoveSpeedUp(percent) => {
-                    commands
-                        .spawn(SpeedUpPoint(*percent))
-                        .insert(Transform::from_xyz(
-                            event.grid_pos.0 as f32 * 32.0,
-                            event.grid_pos.1 as f32 * 32.0,
-                            0.0,
-                        ));
-                }
-                Debuff::Overheat => {
-                    commands
-                        .entity(*entity)
-                        .insert(Overheatable(Timer::from_seconds(20.0, TimerMode::Once)));
-                }
-                Debuff::TurretIncompatible => {
-                    if let TowerType::ChargeShot = tower.variant {
-                        tower.reduce_damage_by(50.0);
-                    }
-                }
-                Debuff::SniperIncompatible => {
-                    if let TowerType::Sniper = tower.variant {
-                        tower.reduce_damage_by(50.0);
-                    }
-                }
-                Debuff::LaserIncompatible => {
-                    if let TowerType::Laser = tower.variant {
-                        tower.reduce_damage_by(50.0);
-                    }
-                }
-                Debuff::MissileIncompatible => {
-                    if let TowerType::Missile = tower.variant {
-                        tower.reduce_damage_by(50.0);
+    if *placements == map.placements {
+        return;
+    }
+    // Visit towers in a fixed order, so modifiers are always combined the same way
+    let mut positions: Vec<_> = map
+        .placements
+        .iter()
+        .map(|(pos, entity)| (*pos, *entity))
+        .collect();
+    positions.sort_by_key(|(pos, _)| *pos);
+    let mut sources = Vec::new();
+    for &(pos, entity) in positions.iter() {
+        let Ok((tower, ..)) = towers.get(entity) else {
+            // Not spawned yet, so try again next tick
+            return;
+        };
+        sources.push(SideEffectSource::new(pos, entity, tower));
+    }
+    *placements = map.placements.clone();
+
+    for &(pos, entity) in positions.iter() {
+        let (mut tower, overheatable, children) = towers.get_mut(entity).unwrap();
+        tower.modifiers = modifiers_at(&tower, pos, &sources);
+        tower.recompute_stats();
+
+        match (tower.overheats(), overheatable.is_some()) {
+            (true, false) => {
+                commands
+                    .entity(entity)
+                    .insert(Overheatable(Timer::from_seconds(20.0, TimerMode::Once)));
+            }
+            (false, true) => {
+                tower.overheating = false;
+                commands.entity(entity).remove::<Overheatable>();
+                for child in children.into_iter().flat_map(|children| children.iter()) {
+                    if overheat_icons.contains(*child) {
+                        commands.entity(*child).despawn_recursive();
                     }
                 }
-                Debuff::TargetClosest => tower.target_mode = TargetMode::Closest,
-                Debuff::TargetRandom => tower.target_mode = TargetMode::Random,
-                _ => (),
             }
+            _ => (),
         }
     }
 }
@@ -200,6 +368,7 @@ pub fn handle_overheat(
     game_assets: Res<GameAssets>,
     game_manager: Res<GameManager>,
     time: Res<Time>,
+    mut rng: ResMut<GameRng>,
     sound_channel: Res<AudioChannel<SoundChannel>>,
     audio_assets: Res<AudioAssets>,
 ) {
@@ -213,7 +382,7 @@ pub fn handle_overheat(
                 tower.overheating = false;
                 overheatable
                     .0
-                    .set_duration(Duration::from_secs_f32(rand::random::<f32>() * 60.0 + 30.0));
+                    .set_duration(Duration::from_secs_f32(rng.gen::<f32>() * 60.0 + 30.0));
                 overheatable.0.reset();
                 for child in children.iter() {
                     if icons.get(*child).is_ok() {
@@ -239,3 +408,94 @@ pub fn handle_overheat(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    const POS: (i8, i8) = (1, 1);
+
+    /// A turret at [`POS`] that can overheat, surrounded by towers whose side effects reach it.
+    fn neighbourhood() -> (Tower, Vec<SideEffectSource>) {
+        let tower = Tower::new(0.4, 1.8, TowerType::ChargeShot, Debuff::Overheat);
+        let mut left = Tower::new(
+            3.0,
+            0.3,
+            TowerType::Sniper,
+            Debuff::ReduceNeighbourDamage(20.0),
+        );
+        left.buff = Some(Buff::BoostNeighbourRate(15.0));
+        let right = Tower::new(0.16, 4.0, TowerType::Laser, Debuff::TurretIncompatible);
+        let row = Tower::new(0.16, 4.0, TowerType::Laser, Debuff::ReduceRowRate(10.0));
+        let column = Tower::new(
+            8.0,
+            0.135,
+            TowerType::Missile,
+            Debuff::ReduceColumnDamage(30.0),
+        );
+        let sources = vec![
+            SideEffectSource::new(POS, Entity::from_raw(0), &tower),
+            SideEffectSource::new((0, 1), Entity::from_raw(1), &left),
+            SideEffectSource::new((2, 1), Entity::from_raw(2), &right),
+            SideEffectSource::new((4, 1), Entity::from_raw(3), &row),
+            SideEffectSource::new((1, 3), Entity::from_raw(4), &column),
+        ];
+        (tower, sources)
+    }
+
+    fn stats(tower: &Tower, sources: &[SideEffectSource]) -> (f32, f32) {
+        let mut tower = tower.clone();
+        tower.modifiers = modifiers_at(&tower, POS, sources);
+        tower.recompute_stats();
+        (tower.damage, tower.rate)
+    }
+
+    #[test]
+    fn order_of_sources_does_not_change_stats() {
+        let (tower, mut sources) = neighbourhood();
+        let expected = stats(&tower, &sources);
+        assert!(expected.0 < tower.base_damage);
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for _ in 0..20 {
+            sources.shuffle(&mut rng);
+            assert_eq!(stats(&tower, &sources), expected);
+        }
+    }
+
+    #[test]
+    fn immune_tower_ignores_its_neighbours() {
+        let (mut tower, mut sources) = neighbourhood();
+        tower.extra_debuffs.push(Debuff::Immune);
+        sources[0] = SideEffectSource::new(POS, Entity::from_raw(0), &tower);
+        let modifiers = modifiers_at(&tower, POS, &sources);
+        // Only its own overheating is left
+        assert_eq!(modifiers.len(), 1);
+        assert_eq!(modifiers[0].source, Entity::from_raw(0));
+        assert_eq!(modifiers[0].kind, ModifierKind::Overheat);
+        assert_eq!(
+            stats(&tower, &sources),
+            (tower.base_damage, tower.base_rate)
+        );
+    }
+
+    #[test]
+    fn removing_a_source_removes_its_modifiers() {
+        let (tower, mut sources) = neighbourhood();
+        let before = stats(&tower, &sources);
+        let removed = sources.remove(1);
+        let modifiers = modifiers_at(&tower, POS, &sources);
+        assert!(modifiers
+            .iter()
+            .all(|modifier| modifier.source != removed.entity));
+        let after = stats(&tower, &sources);
+        assert!(after.0 > before.0);
+        assert!(after.1 < before.1);
+
+        // Putting it back, like undoing a placement, gives the same stats as before
+        sources.push(removed);
+        assert_eq!(stats(&tower, &sources), before);
+    }
+}
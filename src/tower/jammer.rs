@@ -1,22 +1,20 @@
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy::{prelude::*, render::view::NoFrustumCulling, sprite::MaterialMesh2dBundle};
 
-use crate::{grid::Map, state::loading::GameAssets};
+use crate::state::loading::GameAssets;
 
-use super::{debuffs::SpeedUpPoint, RangeIndicator, Tower, TowerPlaced};
+use super::{debuffs::SpeedUpPoint, RangeIndicator, Tower};
 
 #[derive(Component, Debug)]
 pub struct RotatingDish;
 
 pub fn spawn_jammer(
     tower: Tower,
-    mut commands: Commands,
+    commands: &mut Commands,
     grid_pos: (i8, i8),
-    game_assets: Res<GameAssets>,
-    mut event_writer: EventWriter<TowerPlaced>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut map: ResMut<Map>,
-) {
+    game_assets: &GameAssets,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) -> Entity {
     let entity = commands
         .spawn(SpriteBundle {
             texture: game_assets.pivot.clone(),
@@ -27,6 +25,7 @@ pub fn spawn_jammer(
             )),
             ..Default::default()
         })
+        .insert(NoFrustumCulling)
         .insert(tower)
         .with_children(|parent| {
             // Circle used to show the range of the tower
@@ -48,8 +47,6 @@ pub fn spawn_jammer(
                 .insert(RotatingDish);
         })
         .id();
-    map.place_tower(grid_pos, entity).unwrap();
-    event_writer.send(TowerPlaced { grid_pos });
     commands
         .spawn(SpeedUpPoint(-40.0))
         .insert(Transform::from_translation(Vec3::new(
@@ -57,6 +54,7 @@ pub fn spawn_jammer(
             grid_pos.1 as f32 * 32.0,
             1.0,
         )));
+    entity
 }
 
 pub fn rotate_dish(mut query: Query<&mut Transform, With<RotatingDish>>, time: Res<Time>) {
@@ -1,18 +1,30 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy::{prelude::*, render::view::NoFrustumCulling, sprite::MaterialMesh2dBundle};
+use serde::{Deserialize, Serialize};
 
 use crate::{enemies::Enemy, grid::Map, state::loading::GameAssets};
 
-use super::{Tower, TowerPlaced};
+use super::Tower;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
     Left,
     Right,
 }
+impl Direction {
+    /// How a laser facing this way is rotated.
+    pub fn rotation(&self) -> Quat {
+        match self {
+            Direction::Up => Quat::from_rotation_z(std::f32::consts::PI),
+            Direction::Down => Quat::from_rotation_z(0.0),
+            Direction::Left => Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
+            Direction::Right => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+        }
+    }
+}
 
 #[derive(Component, Debug)]
 pub struct Laser {
@@ -61,7 +73,7 @@ pub fn shoot(
                         if let Some(grid_enemies) = map.enemies.get(&(grid_pos.0, current_y)) {
                             for entity in grid_enemies {
                                 if let Ok(mut enemy) = enemies.get_mut(*entity) {
-                                    enemy.current_health -= tower.damage;
+                                    enemy.take_damage(tower.damage);
                                 }
                             }
                         }
@@ -74,7 +86,7 @@ pub fn shoot(
                         if let Some(grid_enemies) = map.enemies.get(&(grid_pos.0, current_y)) {
                             for entity in grid_enemies {
                                 if let Ok(mut enemy) = enemies.get_mut(*entity) {
-                                    enemy.current_health -= tower.damage;
+                                    enemy.take_damage(tower.damage);
                                 }
                             }
                         }
@@ -87,7 +99,7 @@ pub fn shoot(
                         if let Some(grid_enemies) = map.enemies.get(&(current_x, grid_pos.1)) {
                             for entity in grid_enemies {
                                 if let Ok(mut enemy) = enemies.get_mut(*entity) {
-                                    enemy.current_health -= tower.damage;
+                                    enemy.take_damage(tower.damage);
                                 }
                             }
                         }
@@ -100,7 +112,7 @@ pub fn shoot(
                         if let Some(grid_enemies) = map.enemies.get(&(current_x, grid_pos.1)) {
                             for entity in grid_enemies {
                                 if let Ok(mut enemy) = enemies.get_mut(*entity) {
-                                    enemy.current_health -= tower.damage;
+                                    enemy.take_damage(tower.damage);
                                 }
                             }
                         }
@@ -114,22 +126,16 @@ pub fn shoot(
 
 pub fn spawn_laser(
     tower: Tower,
-    mut commands: Commands,
+    commands: &mut Commands,
     grid_pos: (i8, i8),
     direction: Direction,
-    game_assets: Res<GameAssets>,
-    mut event_writer: EventWriter<TowerPlaced>,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<ColorMaterial>>,
-    mut map: ResMut<Map>,
-) {
-    let rotation = match direction {
-        Direction::Up => Quat::from_rotation_z(std::f32::consts::PI),
-        Direction::Down => Quat::from_rotation_z(0.0),
-        Direction::Left => Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
-        Direction::Right => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
-    };
-    let entity = commands
+    game_assets: &GameAssets,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    map: &Map,
+) -> Entity {
+    let rotation = direction.rotation();
+    commands
         .spawn(SpriteBundle {
             texture: game_assets.laser.clone(),
             transform: Transform::from_translation(Vec3::new(
@@ -141,14 +147,13 @@ pub fn spawn_laser(
             ..Default::default()
         })
         .insert(Laser::new(direction, tower.rate))
+        .insert(NoFrustumCulling)
         .insert(tower)
         .with_children(|parent| {
             // Laser beam
-            spawn_laser_beam(parent, grid_pos, direction, meshes, materials, &map);
+            spawn_laser_beam(parent, grid_pos, direction, meshes, materials, map);
         })
-        .id();
-    map.place_tower(grid_pos, entity).unwrap();
-    event_writer.send(TowerPlaced { grid_pos });
+        .id()
 }
 
 #[derive(Component)]
@@ -158,16 +163,11 @@ pub fn spawn_laser_beam(
     parent: &mut ChildBuilder,
     grid_pos: (i8, i8),
     direction: Direction,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
     map: &Map,
 ) {
-    let rotation = match direction {
-        Direction::Up => Quat::from_rotation_z(std::f32::consts::PI),
-        Direction::Down => Quat::from_rotation_z(0.0),
-        Direction::Left => Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
-        Direction::Right => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
-    };
+    let rotation = direction.rotation();
     let (beam_dimensions, beam_location) = match direction {
         Direction::Up => {
             let height = (map.height as i8 - grid_pos.1) as f32 * 32.0 - 16.0;
@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{prelude::*, render::view::NoFrustumCulling};
 use bevy_kira_audio::{AudioChannel, AudioControl};
 
 use crate::{
@@ -11,7 +11,7 @@ use crate::{
     state::loading::GameAssets,
 };
 
-use super::{Tower, TowerPlaced};
+use super::Tower;
 
 #[derive(Component)]
 pub struct Silo {
@@ -20,13 +20,11 @@ pub struct Silo {
 
 pub fn spawn_silo(
     tower: Tower,
-    mut commands: Commands,
+    commands: &mut Commands,
     grid_pos: (i8, i8),
-    game_assets: Res<GameAssets>,
-    mut event_writer: EventWriter<TowerPlaced>,
-    mut map: ResMut<Map>,
-) {
-    let entity = commands
+    game_assets: &GameAssets,
+) -> Entity {
+    commands
         .spawn(SpriteBundle {
             texture: game_assets.silo.clone(),
             transform: Transform::from_translation(Vec3::new(
@@ -39,10 +37,9 @@ pub fn spawn_silo(
         .insert(Silo {
             timer: Timer::from_seconds(1.0 / tower.rate, TimerMode::Once),
         })
+        .insert(NoFrustumCulling)
         .insert(tower)
-        .id();
-    map.place_tower(grid_pos, entity).unwrap();
-    event_writer.send(TowerPlaced { grid_pos });
+        .id()
 }
 
 #[derive(Component)]
@@ -85,6 +82,7 @@ pub fn spawn_missile(
                     )),
                     ..Default::default()
                 })
+                .insert(NoFrustumCulling)
                 .insert(Missile {
                     target: None,
                     damage: tower.damage,
@@ -134,7 +132,7 @@ pub fn handle_missile(
                 }
                 transform.translation += current_direction * missile.speed * time.delta_seconds();
                 if distance < 15.0 {
-                    enemy.current_health -= missile.damage;
+                    enemy.take_damage(missile.damage);
                     commands.entity(entity).despawn();
                     sound_channel.play(audio_assets.explosion.clone());
                 }
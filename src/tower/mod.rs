@@ -1,24 +1,49 @@
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
 use bevy_kira_audio::{AudioChannel, AudioControl};
 use rand::{distributions::Standard, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     audio::{AudioAssets, SoundChannel},
     enemies::Enemy,
     grid::Map,
-    ui::UiData,
+    state::loading::GameAssets,
+    ui::{constants::YELLOW, legacy_mul_f32, UiData},
 };
 
-use self::debuffs::{AddDebuff, Debuff};
+use self::{
+    buffs::{Buff, BUFF_CHANCE},
+    charge_shot::{spawn_charge_shot, ChargeShot},
+    debuffs::{Debuff, Modifier, ModifierKind, SpeedUpPoint},
+    jammer::spawn_jammer,
+    laser::{spawn_laser, Direction},
+    missile::spawn_silo,
+    rarity::Rarity,
+    sniper::{spawn_sniper, Sniper},
+};
 
+pub mod buffs;
 pub mod charge_shot;
 pub mod debuffs;
 pub mod jammer;
 pub mod laser;
 pub mod missile;
+pub mod rarity;
 pub mod sniper;
 
-#[derive(Debug)]
+/// The most times a tower can be upgraded.
+pub const MAX_LEVEL: u8 = 3;
+/// How much each upgrade raises a tower's damage by, in percent.
+pub const UPGRADE_DAMAGE: f32 = 25.0;
+/// How much each upgrade raises a tower's rate of fire by, in percent.
+pub const UPGRADE_RATE: f32 = 10.0;
+/// How many tiles each upgrade adds to the range of turrets and snipers.
+pub const UPGRADE_RANGE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TowerType {
     ChargeShot,
     Laser,
@@ -50,6 +75,25 @@ impl TowerType {
             }
         }
     }
+
+    /// Whether a random tower of this type can roll the debuff.
+    pub fn allows(&self, debuff: &Debuff) -> bool {
+        match self {
+            // Lasers and missile launchers pick their targets their own way
+            TowerType::Laser | TowerType::Missile => {
+                !matches!(debuff, Debuff::TargetClosest | Debuff::TargetRandom)
+            }
+            // Jammers don't shoot, and already slow enemies down
+            TowerType::Jammer => !matches!(
+                debuff,
+                Debuff::TargetClosest
+                    | Debuff::TargetRandom
+                    | Debuff::MoveSpeedUp(_)
+                    | Debuff::Overheat
+            ),
+            TowerType::ChargeShot | TowerType::Sniper => true,
+        }
+    }
 }
 impl Distribution<TowerType> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TowerType {
@@ -64,90 +108,315 @@ impl Distribution<TowerType> for Standard {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TargetMode {
     First,
     Closest,
     Random,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Tower {
+    /// The damage the tower deals, after its upgrades and modifiers.
     pub damage: f32,
+    /// How many times a second the tower attacks, after its upgrades and modifiers.
     pub rate: f32,
+    /// The damage and rate the tower was rolled with. Towers saved before these were kept are
+    /// missing them, and get them from [`Tower::fill_missing_base_stats`].
+    #[serde(default)]
+    pub base_damage: f32,
+    #[serde(default)]
+    pub base_rate: f32,
     pub variant: TowerType,
     pub debuff: Debuff,
+    /// The debuffs rare and epic towers have on top of their first one.
+    #[serde(default)]
+    pub extra_debuffs: Vec<Debuff>,
+    #[serde(default)]
+    pub buff: Option<Buff>,
+    #[serde(default)]
+    pub rarity: Rarity,
     pub overheating: bool,
     pub target_mode: TargetMode,
+    /// How many times the tower has been upgraded.
+    #[serde(default)]
+    pub level: u8,
+    /// What the debuffs of this tower and the towers around it do to it. These are worked out
+    /// again whenever a tower is placed or removed.
+    #[serde(skip)]
+    pub modifiers: Vec<Modifier>,
 }
 impl Tower {
     pub fn new(damage: f32, rate: f32, variant: TowerType, debuff: Debuff) -> Self {
+        let target_mode = debuff.target_mode().unwrap_or(TargetMode::First);
         Self {
             damage,
             rate,
+            base_damage: damage,
+            base_rate: rate,
             variant,
             debuff,
+            extra_debuffs: Vec::new(),
+            buff: None,
+            rarity: Rarity::Common,
             overheating: false,
-            target_mode: TargetMode::First,
+            target_mode,
+            level: 0,
+            modifiers: Vec::new(),
         }
     }
 
-    pub fn new_random() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new_random<R: Rng>(rng: &mut R) -> Self {
         let variant: TowerType = rng.gen();
-        match variant {
-            TowerType::ChargeShot => Self::new(
-                (rng.gen_range(0.3..=0.5) * 100.0_f32).round() / 100.0,
-                1.8,
-                variant,
-                rand::random(),
-            ),
-            TowerType::Laser => loop {
-                let debuff: Debuff = rand::random();
-                match debuff {
-                    Debuff::TargetClosest | Debuff::TargetRandom => {
-                        // These debuffs are not compatible with laser
-                        continue;
-                    }
-                    _ => break Self::new(0.16, 4.0, variant, debuff),
-                }
-            },
-            TowerType::Sniper => Self::new(3.0, 0.3, variant, rand::random()),
-            TowerType::Jammer => loop {
-                let debuff: Debuff = rand::random();
-                match debuff {
-                    Debuff::TargetClosest
-                    | Debuff::TargetRandom
-                    | Debuff::MoveSpeedUp(_)
-                    | Debuff::Overheat => {
-                        // These debuffs are not compatible with jammer
-                        continue;
-                    }
-                    _ => break Self::new(0.0, 0.0, variant, debuff),
-                }
-            },
-            TowerType::Missile => loop {
-                let debuff: Debuff = rand::random();
-                match debuff {
-                    Debuff::TargetClosest | Debuff::TargetRandom => {
-                        // These debuffs are not compatible with missile launcher
-                        continue;
-                    }
-                    _ => break Self::new(8.0, 0.135, variant, debuff),
-                }
-            },
+        let rarity: Rarity = rng.gen();
+        let (damage, rate) = match variant {
+            TowerType::ChargeShot => ((rng.gen_range(0.3..=0.5) * 100.0_f32).round() / 100.0, 1.8),
+            TowerType::Laser => (0.16, 4.0),
+            TowerType::Sniper => (3.0, 0.3),
+            TowerType::Jammer => (0.0, 0.0),
+            TowerType::Missile => (8.0, 0.135),
+        };
+        let mut debuffs: Vec<Debuff> = Vec::new();
+        while debuffs.len() < rarity.side_effects() {
+            let debuff: Debuff = rng.gen();
+            if variant.allows(&debuff) && !debuffs.iter().any(|other| debuff.conflicts_with(other))
+            {
+                debuffs.push(debuff);
+            }
+        }
+        let multiplier = rarity.stat_multiplier();
+        let mut tower = Self::new(
+            damage * multiplier,
+            rate * multiplier,
+            variant,
+            debuffs.remove(0),
+        );
+        tower.rarity = rarity;
+        for debuff in debuffs {
+            if let Some(target_mode) = debuff.target_mode() {
+                tower.target_mode = target_mode;
+            }
+            tower.extra_debuffs.push(debuff);
+        }
+        if rng.gen_bool(BUFF_CHANCE) {
+            tower.buff = Some(rng.gen());
+        }
+        tower
+    }
+
+    /// All of the tower's debuffs.
+    pub fn debuffs(&self) -> impl Iterator<Item = &Debuff> {
+        std::iter::once(&self.debuff).chain(self.extra_debuffs.iter())
+    }
+
+    /// Whether the tower ignores what the towers around it do.
+    pub fn is_immune(&self) -> bool {
+        self.debuffs()
+            .any(|debuff| matches!(debuff, Debuff::Immune))
+    }
+
+    /// Towers saved before their base stats were kept only have the stats they were saved with,
+    /// so start from those.
+    pub fn fill_missing_base_stats(&mut self) {
+        if self.base_damage == 0.0 {
+            self.base_damage = self.damage;
+        }
+        if self.base_rate == 0.0 {
+            self.base_rate = self.rate;
+        }
+    }
+
+    /// Work out the tower's damage and rate from its base stats, upgrades and modifiers.
+    pub fn recompute_stats(&mut self) {
+        let level = self.level as i32;
+        let damage = self.base_damage * (1.0 + UPGRADE_DAMAGE / 100.0).powi(level);
+        let rate = self.base_rate * (1.0 + UPGRADE_RATE / 100.0).powi(level);
+        self.damage = damage * self.modifier_multiplier(ModifierKind::Damage);
+        self.rate = rate * self.modifier_multiplier(ModifierKind::Rate);
+    }
+
+    /// What the modifiers of the given kind multiply a stat by. The percentages are applied
+    /// smallest first, so the result doesn't depend on the order the modifiers are in.
+    fn modifier_multiplier(&self, kind: ModifierKind) -> f32 {
+        let mut magnitudes: Vec<f32> = self
+            .modifiers
+            .iter()
+            .filter(|modifier| modifier.kind == kind)
+            .map(|modifier| modifier.magnitude)
+            .collect();
+        magnitudes.sort_by(f32::total_cmp);
+        magnitudes.iter().fold(1.0, |multiplier, magnitude| {
+            multiplier * (1.0 + magnitude / 100.0)
+        })
+    }
+
+    /// Whether anything is making the tower randomly overheat.
+    pub fn overheats(&self) -> bool {
+        self.modifiers
+            .iter()
+            .any(|modifier| modifier.kind == ModifierKind::Overheat)
+    }
+
+    /// How many upgrade points selling the tower gives back: half of what its upgrades cost.
+    pub fn sell_refund(&self) -> u32 {
+        let spent = (1..=self.level as u32).sum::<u32>();
+        spent / 2
+    }
+
+    /// What the next upgrade costs in upgrade points, or `None` if the tower can't be upgraded
+    /// any further. Jammers don't deal damage, so they have nothing to upgrade.
+    pub fn upgrade_cost(&self) -> Option<u32> {
+        if matches!(self.variant, TowerType::Jammer) || self.level >= MAX_LEVEL {
+            None
+        } else {
+            Some(self.level as u32 + 1)
         }
     }
 
-    pub fn reduce_damage_by(&mut self, percent: f32) {
-        let reduction = self.damage * (percent / 100.0);
-        self.damage -= reduction;
+    /// Raise the tower to the next level.
+    pub fn upgrade(&mut self) {
+        self.level += 1;
+        self.recompute_stats();
+    }
+
+    /// How far the tower reaches in tiles, after its upgrades and modifiers. `None` for towers
+    /// that don't have a limited range.
+    pub fn range(&self) -> Option<f32> {
+        let base = match self.variant {
+            TowerType::ChargeShot => 1.0,
+            TowerType::Sniper => 3.0,
+            _ => return None,
+        };
+        let extension = self
+            .modifiers
+            .iter()
+            .filter(|modifier| modifier.kind == ModifierKind::Range)
+            .map(|modifier| modifier.magnitude)
+            .sum::<f32>();
+        Some(base + self.level as f32 * UPGRADE_RANGE + extension)
     }
+}
+
+/// Spawn a tower of any type at the given grid position.
+pub fn spawn_tower(
+    tower: Tower,
+    commands: &mut Commands,
+    grid_pos: (i8, i8),
+    game_assets: &GameAssets,
+    event_writer: &mut EventWriter<TowerPlaced>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    map: &mut Map,
+) {
+    let entity = spawn_tower_entity(
+        tower,
+        commands,
+        grid_pos,
+        Direction::Down,
+        game_assets,
+        meshes,
+        materials,
+        map,
+    );
+    map.place_tower(grid_pos, entity).unwrap();
+    event_writer.send(TowerPlaced { grid_pos });
+}
 
-    pub fn reduce_rate_by(&mut self, percent: f32) {
-        let reduction = self.rate * (percent / 100.0);
-        self.rate -= reduction;
+/// Spawn a tower's entity without adding it to the map. Its debuffs take effect once it's on
+/// the map. `direction` is only used by lasers.
+pub fn spawn_tower_entity(
+    tower: Tower,
+    commands: &mut Commands,
+    grid_pos: (i8, i8),
+    direction: Direction,
+    game_assets: &GameAssets,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    map: &Map,
+) -> Entity {
+    let level = tower.level;
+    // Enemies near the tower are sped up by its debuff, or slowed down by its buff
+    let speed_ups = tower
+        .debuffs()
+        .filter_map(|debuff| match debuff {
+            Debuff::MoveSpeedUp(percent) => Some(*percent),
+            _ => None,
+        })
+        .chain(match tower.buff {
+            Some(Buff::SlowAura(percent)) => Some(-percent),
+            _ => None,
+        });
+    for percent in speed_ups {
+        commands
+            .spawn(SpeedUpPoint(percent))
+            .insert(Transform::from_xyz(
+                grid_pos.0 as f32 * 32.0,
+                grid_pos.1 as f32 * 32.0,
+                0.0,
+            ));
+    }
+    let entity = match tower.variant {
+        TowerType::ChargeShot => {
+            spawn_charge_shot(tower, commands, grid_pos, game_assets, meshes, materials)
+        }
+        TowerType::Laser => spawn_laser(
+            tower,
+            commands,
+            grid_pos,
+            direction,
+            game_assets,
+            meshes,
+            materials,
+            map,
+        ),
+        TowerType::Sniper => {
+            spawn_sniper(tower, commands, grid_pos, game_assets, meshes, materials)
+        }
+        TowerType::Jammer => {
+            spawn_jammer(tower, commands, grid_pos, game_assets, meshes, materials)
+        }
+        TowerType::Missile => spawn_silo(tower, commands, grid_pos, game_assets),
+    };
+    if level > 0 {
+        commands.entity(entity).with_children(|parent| {
+            spawn_tier_indicator(parent, level, direction.rotation(), meshes, materials);
+        });
     }
+    entity
+}
+
+/// Holds the pips that show how many times a tower has been upgraded.
+#[derive(Component)]
+pub struct TierIndicator;
+
+/// Show a pip along the bottom of the tower for each upgrade level. `rotation` is the tower's
+/// own rotation, which the pips are turned back from so they stay upright on lasers.
+pub fn spawn_tier_indicator(
+    parent: &mut ChildBuilder,
+    level: u8,
+    rotation: Quat,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    let mesh = meshes.add(Rectangle::new(5.0, 5.0));
+    let material = materials.add(ColorMaterial::from(legacy_mul_f32(YELLOW, 3.0)));
+    parent
+        .spawn(SpatialBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 6.0).with_rotation(rotation.inverse()),
+            ..Default::default()
+        })
+        .insert(TierIndicator)
+        .with_children(|parent| {
+            for i in 0..level {
+                let x = (i as f32 - (level - 1) as f32 / 2.0) * 8.0;
+                parent.spawn(MaterialMesh2dBundle {
+                    mesh: mesh.clone().into(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(x, -12.0, 0.0),
+                    ..Default::default()
+                });
+            }
+        });
 }
 
 #[derive(Debug, Event)]
@@ -155,234 +424,74 @@ pub struct TowerPlaced {
     pub grid_pos: (i8, i8),
 }
 
+/// Sent once a tower has been sold and its debuffs undone.
+#[derive(Debug, Event)]
+pub struct TowerRemoved {
+    pub grid_pos: (i8, i8),
+}
+
 pub fn handle_tower_placement(
     mut events: EventReader<TowerPlaced>,
-    mut debuff_events: EventWriter<AddDebuff>,
     mut ui_data: ResMut<UiData>,
-    query: Query<&Tower>,
-    map: Res<Map>,
     sound_channel: Res<AudioChannel<SoundChannel>>,
     audio_assets: Res<AudioAssets>,
 ) {
     for event in events.read() {
         sound_channel.play(audio_assets.place.clone());
-        let tower = query
-            .get(
-                *map.placements
-                    .get(&event.grid_pos)
-                    .expect("TowerPlaced event with invalid position"),
-            )
-            .expect("Tower entity not found");
-
         ui_data.selected_pos = Some(event.grid_pos);
-        let (x, y) = event.grid_pos;
-        // Apply debuff to self
-        match &tower.debuff {
-            Debuff::MoveSpeedUp(percent) => {
-                debuff_events.send(AddDebuff {
-                    grid_pos: (x, y),
-                    debuff: Debuff::MoveSpeedUp(*percent),
-                });
-            }
-            Debuff::Overheat => {
-                debuff_events.send(AddDebuff {
-                    grid_pos: (x, y),
-                    debuff: Debuff::Overheat,
-                });
-            }
-            Debuff::TargetClosest => {
-                debuff_events.send(AddDebuff {
-                    grid_pos: (x, y),
-                    debuff: Debuff::TargetClosest,
-                });
-            }
-            Debuff::TargetRandom => {
-                debuff_events.send(AddDebuff {
-                    grid_pos: (x, y),
-                    debuff: Debuff::TargetRandom,
-                });
-            }
-            _ => (),
-        }
-        // Apply debuff to neighbours
-        for &(dx, dy) in &[(0, 1), (0, -1), (1, 0), (-1, 0)] {
-            match &tower.debuff {
-                Debuff::ReduceNeighbourDamage(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::ReduceNeighbourDamage(*percent),
-                    });
-                }
-                Debuff::ReduceNeighbourRate(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::ReduceNeighbourRate(*percent),
-                    });
-                }
-                Debuff::TurretIncompatible => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::TurretIncompatible,
-                    });
-                }
-                Debuff::SniperIncompatible => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::SniperIncompatible,
-                    });
-                }
-                Debuff::LaserIncompatible => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::LaserIncompatible,
-                    });
-                }
-                Debuff::MissileIncompatible => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x + dx, y + dy),
-                        debuff: Debuff::MissileIncompatible,
-                    });
-                }
-                _ => {}
-            }
-        }
-        // Apply debuff to row
-        for x2 in 0..=map.width {
-            if x2 == x as u8 {
-                // Skip self
-                continue;
-            }
-            match &tower.debuff {
-                Debuff::RowOverheat => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x2.try_into().unwrap(), y),
-                        debuff: Debuff::Overheat,
-                    });
-                }
-                Debuff::ReduceRowDamage(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x2.try_into().unwrap(), y),
-                        debuff: Debuff::ReduceNeighbourDamage(*percent),
-                    });
-                }
-                Debuff::ReduceRowRate(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x2.try_into().unwrap(), y),
-                        debuff: Debuff::ReduceNeighbourRate(*percent),
-                    });
-                }
-                _ => (),
-            }
-        }
-        // Apply debuff to column
-        for y2 in 0..=map.height {
-            if y2 == y as u8 {
-                // Skip self
-                continue;
-            }
-            match &tower.debuff {
-                Debuff::ColumnOverheat => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x, y2.try_into().unwrap()),
-                        debuff: Debuff::Overheat,
-                    });
-                }
-                Debuff::ReduceColumnDamage(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x, y2.try_into().unwrap()),
-                        debuff: Debuff::ReduceNeighbourDamage(*percent),
-                    });
-                }
-                Debuff::ReduceColumnRate(percent) => {
-                    debuff_events.send(AddDebuff {
-                        grid_pos: (x, y2.try_into().unwrap()),
-                        debuff: Debuff::ReduceNeighbourRate(*percent),
-                    });
-                }
-                _ => (),
-            }
-        }
-        // Apply neighbour debuff to self
-        for &(dx, dy) in &[(0, 1), (0, -1), (1, 0), (-1, 0)] {
-            if let Some(entity) = map.placements.get(&(x + dx, y + dy)) {
-                let neighbour_tower = query.get(*entity).expect("Tower entity not found");
-                match &neighbour_tower.debuff {
-                    Debuff::ReduceNeighbourDamage(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourDamage(*percent),
-                        });
-                    }
-                    Debuff::ReduceNeighbourRate(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourRate(*percent),
-                        });
-                    }
-                    _ => {}
-                }
-            }
+    }
+}
+
+pub fn handle_tower_removal(
+    mut events: EventReader<TowerRemoved>,
+    mut ui_data: ResMut<UiData>,
+    sound_channel: Res<AudioChannel<SoundChannel>>,
+    audio_assets: Res<AudioAssets>,
+) {
+    for event in events.read() {
+        sound_channel.play(audio_assets.blip2.clone());
+        if ui_data.selected_pos == Some(event.grid_pos) {
+            ui_data.selected_pos = None;
         }
-        // Apply row debuffs to self
-        for x2 in 0..=map.width {
-            if x2 == x as u8 {
-                // Skip self
-                continue;
-            }
-            if let Some(entity) = map.placements.get(&(x2.try_into().unwrap(), y)) {
-                let neighbour_tower = query.get(*entity).expect("Tower entity not found");
-                match &neighbour_tower.debuff {
-                    Debuff::RowOverheat => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::Overheat,
-                        });
-                    }
-                    Debuff::ReduceRowDamage(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourDamage(*percent),
-                        });
-                    }
-                    Debuff::ReduceRowRate(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourRate(*percent),
-                        });
-                    }
-                    _ => (),
-                }
-            }
+        // Redraw the sidebar even if another tower is selected, since its stats may have changed
+        ui_data.set_changed();
+    }
+}
+
+/// Keep the range of turrets and snipers, and their range indicators, in step with their
+/// upgrades and modifiers.
+pub fn update_ranges(
+    mut commands: Commands,
+    mut towers: Query<
+        (
+            &Tower,
+            &Children,
+            Option<&mut ChargeShot>,
+            Option<&mut Sniper>,
+        ),
+        Changed<Tower>,
+    >,
+    range_indicators: Query<(), With<RangeIndicator>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (tower, children, charge_shot, sniper) in towers.iter_mut() {
+        let Some(range) = tower.range() else {
+            continue;
+        };
+        let current = match (charge_shot, sniper) {
+            (Some(charge_shot), _) => &mut charge_shot.into_inner().range,
+            (None, Some(sniper)) => &mut sniper.into_inner().range,
+            (None, None) => continue,
+        };
+        if *current == range {
+            continue;
         }
-        // Apply column debuffs to self
-        for y2 in 0..=map.height {
-            if y2 == y as u8 {
-                // Skip self
-                continue;
-            }
-            if let Some(entity) = map.placements.get(&(x, y2.try_into().unwrap())) {
-                let neighbour_tower = query.get(*entity).expect("Tower entity not found");
-                match &neighbour_tower.debuff {
-                    Debuff::ColumnOverheat => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::Overheat,
-                        });
-                    }
-                    Debuff::ReduceColumnDamage(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourDamage(*percent),
-                        });
-                    }
-                    Debuff::ReduceColumnRate(percent) => {
-                        debuff_events.send(AddDebuff {
-                            grid_pos: (x, y),
-                            debuff: Debuff::ReduceNeighbourRate(*percent),
-                        });
-                    }
-                    _ => (),
-                }
+        *current = range;
+        for child in children.iter() {
+            if range_indicators.contains(*child) {
+                commands
+                    .entity(*child)
+                    .insert(Mesh2dHandle(meshes.add(Circle::new((range + 0.5) * 32.0))));
             }
         }
     }
@@ -413,7 +522,7 @@ pub fn handle_projectiles(
             transform.translation += direction * projectile.speed * time.delta_seconds();
             if transform.translation.distance(enemy_transform.translation) < 15.0 {
                 // Hit enemy
-                enemy.current_health -= projectile.damage;
+                enemy.take_damage(projectile.damage);
                 commands.entity(entity).despawn();
             }
         } else {
@@ -0,0 +1,48 @@
+use rand::{distributions::Standard, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+
+/// How rare a random tower is. Rarer towers have better base stats, but more side effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Rarity {
+    #[default]
+    Common,
+    Rare,
+    Epic,
+}
+impl Rarity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rarity::Common => "Common",
+            Rarity::Rare => "Rare",
+            Rarity::Epic => "Epic",
+        }
+    }
+
+    /// What the tower's base damage and rate are multiplied by.
+    pub fn stat_multiplier(&self) -> f32 {
+        match self {
+            Rarity::Common => 1.0,
+            Rarity::Rare => 1.3,
+            Rarity::Epic => 1.6,
+        }
+    }
+
+    /// How many debuffs the tower rolls.
+    pub fn side_effects(&self) -> usize {
+        match self {
+            Rarity::Common => 1,
+            Rarity::Rare => 2,
+            Rarity::Epic => 3,
+        }
+    }
+}
+impl Distribution<Rarity> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Rarity {
+        match rng.gen_range(0..=19) {
+            0..=13 => Rarity::Common,
+            14..=17 => Rarity::Rare,
+            18..=19 => Rarity::Epic,
+            _ => unreachable!(),
+        }
+    }
+}
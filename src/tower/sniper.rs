@@ -1,15 +1,16 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy::{prelude::*, render::view::NoFrustumCulling, sprite::MaterialMesh2dBundle};
 use bevy_kira_audio::{AudioChannel, AudioControl};
 use rand::seq::SliceRandom;
 
-use super::{Projectile, RangeIndicator, RotatingTurret, TargetMode, Tower, TowerPlaced};
+use super::{Projectile, RangeIndicator, RotatingTurret, TargetMode, Tower};
 use crate::ui::legacy_mul_f32;
 use crate::{
     audio::{AudioAssets, SoundChannel},
     enemies::Enemy,
     grid::Map,
+    rng::GameRng,
     state::loading::GameAssets,
     ui::constants::RED,
 };
@@ -39,6 +40,7 @@ pub fn shoot(
     map: Res<Map>,
     time: Res<Time>,
     game_assets: Res<GameAssets>,
+    mut rng: ResMut<GameRng>,
     sound_channel: Res<AudioChannel<SoundChannel>>,
     audio_assets: Res<AudioAssets>,
 ) {
@@ -107,7 +109,6 @@ pub fn shoot(
                     }
                 }
                 TargetMode::Random => {
-                    let mut rng = rand::thread_rng();
                     let mut possible_targets = Vec::new();
                     for x in -max_range..=max_range {
                         for y in -max_range..=max_range {
@@ -129,7 +130,7 @@ pub fn shoot(
                         }
                     }
                     if !possible_targets.is_empty() {
-                        target_enemy = Some(*possible_targets.choose(&mut rng).unwrap());
+                        target_enemy = Some(*possible_targets.choose(&mut *rng).unwrap());
                     }
                 }
             }
@@ -150,6 +151,7 @@ pub fn shoot(
                             transform: Transform::from_translation(projectile_pos),
                             ..Default::default()
                         })
+                        .insert(NoFrustumCulling)
                         .insert(Projectile {
                             damage: tower.damage,
                             speed: 350.0,
@@ -172,15 +174,14 @@ pub fn shoot(
 
 pub fn spawn_sniper(
     tower: Tower,
-    mut commands: Commands,
+    commands: &mut Commands,
     grid_pos: (i8, i8),
-    game_assets: Res<GameAssets>,
-    mut event_writer: EventWriter<TowerPlaced>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut map: ResMut<Map>,
-) {
-    let entity = commands
+    game_assets: &GameAssets,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) -> Entity {
+    let range = tower.range().expect("Snipers have a range");
+    commands
         .spawn(SpriteBundle {
             texture: game_assets.pivot.clone(),
             transform: Transform::from_translation(Vec3::new(
@@ -190,13 +191,14 @@ pub fn spawn_sniper(
             )),
             ..Default::default()
         })
-        .insert(Sniper::new(3.0, tower.rate))
+        .insert(Sniper::new(range, tower.rate))
+        .insert(NoFrustumCulling)
         .insert(tower)
         .with_children(|parent| {
             // Circle used to show the range of the tower
             parent
                 .spawn(MaterialMesh2dBundle {
-                    mesh: meshes.add(Circle::new(3.5 * 32.0)).into(),
+                    mesh: meshes.add(Circle::new((range + 0.5) * 32.0)).into(),
                     material: materials.add(ColorMaterial::from(Color::srgba(0.8, 0.4, 0.4, 0.2))),
                     transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
                     visibility: Visibility::Hidden,
@@ -211,7 +213,5 @@ pub fn spawn_sniper(
                 })
                 .insert(RotatingTurret);
         })
-        .id();
-    map.place_tower(grid_pos, entity).unwrap();
-    event_writer.send(TowerPlaced { grid_pos });
+        .id()
 }
@@ -13,3 +13,5 @@ pub const BUTTON_TEXT_COLOR: Color = Color::srgb(0.77, 0.78, 0.78);
 pub const RED: Color = Color::srgb(0.8, 0.4, 0.4);
 pub const GREEN: Color = Color::srgb(0.41, 0.74, 0.41);
 pub const BLUE: Color = Color::srgb(0.4, 0.4, 0.8);
+pub const YELLOW: Color = Color::srgb(0.86, 0.76, 0.36);
+pub const PURPLE: Color = Color::srgb(0.65, 0.42, 0.82);
@@ -3,8 +3,9 @@ use bevy_kira_audio::{AudioChannel, AudioControl};
 
 use crate::{
     audio::{AudioAssets, SoundChannel},
+    grid::Map,
     state::loading::GameAssets,
-    tower::{Tower, TowerType},
+    tower::{rarity::Rarity, Tower, TowerType},
 };
 
 use super::{constants::*, tower_options::TowerOption, UiState, UiStateResource};
@@ -70,10 +71,12 @@ pub fn draw_tower_card(
 
             margin: UiRect::all(Val::Px(10.0)),
             padding: UiRect::all(Val::Px(5.0)),
+            border: UiRect::all(Val::Px(2.0)),
             flex_direction: FlexDirection::Column,
             ..Default::default()
         },
         background_color: CARD_BACKGROUND_COLOR.into(),
+        border_color: rarity_color(tower.rarity).into(),
         ..Default::default()
     });
     if is_inventory {
@@ -163,7 +166,7 @@ pub fn draw_tower_card(
             .spawn(NodeBundle {
                 style: Style {
                     width: Val::Percent(100.0),
-                    height: Val::Px(100.0),
+                    height: Val::Px(80.0),
                     ..Default::default()
                 },
                 ..Default::default()
@@ -171,24 +174,80 @@ pub fn draw_tower_card(
             .with_children(|parent| {
                 parent.spawn(TextBundle {
                     text: Text::from_section(
-                        format!("Side effect: {}", tower.debuff.description()),
+                        side_effect_text(tower),
                         TextStyle {
-                            font,
-                            font_size: 20.0,
+                            font: font.clone(),
+                            // Make room for the extra side effects of rarer towers
+                            font_size: match tower.extra_debuffs.len() {
+                                0 => 20.0,
+                                1 => 15.0,
+                                _ => 12.0,
+                            },
                             color: RED,
                         },
                     ),
                     style: Style {
                         max_width: Val::Px(190.0),
-                        max_height: Val::Px(120.0),
+                        max_height: Val::Px(80.0),
                         ..Default::default()
                     },
                     ..Default::default()
                 });
             });
+        // Buff description
+        if let Some(buff) = &tower.buff {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Px(60.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            format!("Bonus: {}", buff.description()),
+                            TextStyle {
+                                font,
+                                font_size: 17.0,
+                                color: GREEN,
+                            },
+                        ),
+                        style: Style {
+                            max_width: Val::Px(190.0),
+                            max_height: Val::Px(60.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        }
     });
 }
 
+/// The colour of the border around a tower's card.
+pub fn rarity_color(rarity: Rarity) -> Color {
+    match rarity {
+        Rarity::Common => CARD_BACKGROUND_COLOR_HOVER,
+        Rarity::Rare => BLUE,
+        Rarity::Epic => PURPLE,
+    }
+}
+
+/// Describes every debuff of a tower.
+pub fn side_effect_text(tower: &Tower) -> String {
+    if tower.extra_debuffs.is_empty() {
+        return format!("Side effect: {}", tower.debuff.description());
+    }
+    let descriptions: Vec<_> = tower
+        .debuffs()
+        .map(|debuff| format!("- {}", debuff.description()))
+        .collect();
+    format!("Side effects:\n{}", descriptions.join("\n"))
+}
+
 pub fn handle_inventory_buttons(
     mut ui_state: ResMut<UiStateResource>,
     mut query: Query<(&InventoryTower, &Interaction, &mut BackgroundColor), Changed<Interaction>>,
@@ -282,20 +341,27 @@ pub fn create_ghost(
 }
 
 pub fn handle_ghost(
-    mut query: Query<&mut Transform, With<Ghost>>,
+    mut query: Query<(&mut Transform, &mut Sprite), With<Ghost>>,
     windows: Query<&Window>,
     camera: Query<(&Camera, &GlobalTransform)>,
+    map: Res<Map>,
 ) {
-    for mut transform in query.iter_mut() {
+    for (mut transform, mut sprite) in query.iter_mut() {
         if let Ok(window) = windows.get_single() {
             let (camera, camera_transform) = camera.get_single().unwrap();
             if let Some(mouse_position) = window.cursor_position() {
                 if let Some(world_position) =
                     camera.viewport_to_world(camera_transform, mouse_position)
                 {
-                    let x = (world_position.origin.x / 32.0).round() * 32.0;
-                    let y = (world_position.origin.y / 32.0).round() * 32.0;
-                    transform.translation = Vec3::new(x, y, 6.0);
+                    let grid_pos = Map::get_grid_pos(world_position.origin.truncate());
+                    transform.translation =
+                        Vec3::new(grid_pos.0 as f32 * 32.0, grid_pos.1 as f32 * 32.0, 6.0);
+                    // Tint the ghost red where the tower can't be placed
+                    sprite.color = if map.is_valid_placement(grid_pos) {
+                        Color::srgba(1.0, 1.0, 1.0, 0.5)
+                    } else {
+                        Color::srgba(1.0, 0.3, 0.3, 0.5)
+                    };
                 }
             }
         }
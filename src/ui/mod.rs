@@ -4,9 +4,11 @@ use crate::{input::HoverPosition, state::loading::GameAssets, tower::Tower};
 
 pub mod constants;
 pub mod inventory;
+pub mod overlay;
 pub mod sidebar;
 pub mod statusbar;
 pub mod tower_options;
+pub mod wave_preview;
 
 #[derive(Default, Debug)]
 pub enum UiState {
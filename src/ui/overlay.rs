@@ -0,0 +1,272 @@
+//! Tints the grid to show what a tower's side effects would do before it's placed: which tiles
+//! it reaches, which towers it would hit and by how much, and what the towers already on the
+//! map would do to it.
+
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+
+use crate::{
+    grid::Map,
+    input::HoverPosition,
+    state::loading::GameAssets,
+    tower::{
+        buffs::Buff,
+        debuffs::{
+            modifiers_at, Debuff, Modifier, ModifierKind, SideEffectSource, SPEED_UP_RADIUS,
+        },
+        Tower,
+    },
+};
+
+use super::{
+    constants::*,
+    inventory::{Inventory, InventoryTower},
+    UiState, UiStateResource,
+};
+
+/// Holds everything the overlay draws, so it can be cleared in one go.
+#[derive(Component)]
+pub struct SideEffectOverlay;
+
+/// Tiles the previewed tower's side effect reaches.
+const AREA_COLOR: Color = Color::srgba(1.0, 0.6, 0.2, 0.25);
+/// Towers the previewed tower's side effect would change.
+const HIT_COLOR: Color = Color::srgba(1.0, 0.2, 0.2, 0.45);
+
+/// Tiles the previewed tower's buff reaches.
+const BUFF_AREA_COLOR: Color = Color::srgba(0.2, 1.0, 0.4, 0.2);
+/// Towers the previewed tower's buff would help.
+const BUFF_HIT_COLOR: Color = Color::srgba(0.2, 1.0, 0.2, 0.45);
+
+/// What the overlay is showing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preview {
+    /// The inventory tower with this index, at the given tile.
+    Placing(usize, (i8, i8)),
+    /// The inventory tower with this index, on every tile it could be placed on.
+    Card(usize),
+}
+
+pub fn draw_side_effect_overlay(
+    mut commands: Commands,
+    overlay: Query<Entity, With<SideEffectOverlay>>,
+    cards: Query<(&InventoryTower, &Interaction)>,
+    towers: Query<&Tower>,
+    ui_state: Res<UiStateResource>,
+    inventory: Res<Inventory>,
+    hover_pos: Res<HoverPosition>,
+    map: Res<Map>,
+    game_assets: Res<GameAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut shown: Local<Option<(Preview, usize)>>,
+) {
+    // A hovered card takes priority, so cards can be compared while placing a tower
+    let hovered_card = cards
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Hovered)
+        .map(|(card, _)| card.index);
+    let preview = match (hovered_card, &ui_state.state, hover_pos.0) {
+        (Some(index), ..) => Some(Preview::Card(index)),
+        (None, UiState::PlacingTower(index), Some(pos)) => Some(Preview::Placing(*index, pos)),
+        _ => None,
+    };
+    // Placing or selling towers changes what the preview shows
+    let current = preview.map(|preview| (preview, map.placements.len()));
+    if current == *shown && !inventory.is_changed() {
+        return;
+    }
+    *shown = current;
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(preview) = preview else {
+        return;
+    };
+    let index = match preview {
+        Preview::Placing(index, _) | Preview::Card(index) => index,
+    };
+    let Some(tower) = inventory.towers.get(index) else {
+        return;
+    };
+
+    let mut sources: Vec<_> = map
+        .placements
+        .iter()
+        .filter_map(|(pos, entity)| {
+            Some(SideEffectSource::new(
+                *pos,
+                *entity,
+                towers.get(*entity).ok()?,
+            ))
+        })
+        .collect();
+    sources.sort_by_key(|source| source.pos);
+
+    let tile = meshes.add(Rectangle::new(32.0, 32.0));
+    let mut overlay = commands.spawn((SpatialBundle::default(), SideEffectOverlay));
+    overlay.with_children(|parent| {
+        let mut tint = |pos: (i8, i8), color: Color| {
+            parent.spawn(MaterialMesh2dBundle {
+                mesh: tile.clone().into(),
+                material: materials.add(ColorMaterial::from(color)),
+                transform: Transform::from_xyz(pos.0 as f32 * 32.0, pos.1 as f32 * 32.0, 3.5),
+                ..Default::default()
+            });
+        };
+        let mut labels = Vec::new();
+        match preview {
+            Preview::Placing(_, pos) => {
+                // Where the side effects reach, and what they do to the towers there
+                let mut hits: BTreeMap<(i8, i8), Vec<String>> = BTreeMap::new();
+                for target_pos in map_tiles(&map) {
+                    let mut in_area = false;
+                    for debuff in tower.debuffs() {
+                        let Some(area) = debuff.area() else {
+                            continue;
+                        };
+                        if !area.contains(pos, target_pos) {
+                            continue;
+                        }
+                        in_area = true;
+                        let hit = map
+                            .placements
+                            .get(&target_pos)
+                            .and_then(|entity| towers.get(*entity).ok())
+                            .filter(|target| !target.is_immune())
+                            .and_then(|target| debuff.modifier_on(pos, target, target_pos));
+                        if let Some((kind, magnitude)) = hit {
+                            let modifier = Modifier {
+                                source: Entity::PLACEHOLDER,
+                                kind,
+                                magnitude,
+                            };
+                            hits.entry(target_pos)
+                                .or_default()
+                                .push(modifier.description());
+                        }
+                    }
+                    if in_area && !hits.contains_key(&target_pos) {
+                        tint(target_pos, AREA_COLOR);
+                    }
+                }
+                for (target_pos, descriptions) in hits {
+                    tint(target_pos, HIT_COLOR);
+                    labels.push((target_pos, descriptions.join("\n"), RED));
+                }
+                // Where the buff reaches, and which towers it would help
+                if let Some(buff) = &tower.buff {
+                    let area = buff.area();
+                    for target_pos in map_tiles(&map).filter(|target| area.contains(pos, *target)) {
+                        let hit = map
+                            .placements
+                            .get(&target_pos)
+                            .and_then(|entity| towers.get(*entity).ok())
+                            .filter(|target| !target.is_immune())
+                            .and_then(|target| buff.modifier_on(pos, target, target_pos));
+                        match hit {
+                            Some((kind, magnitude)) => {
+                                tint(target_pos, BUFF_HIT_COLOR);
+                                let modifier = Modifier {
+                                    source: Entity::PLACEHOLDER,
+                                    kind,
+                                    magnitude,
+                                };
+                                labels.push((target_pos, modifier.description(), GREEN));
+                            }
+                            None => tint(target_pos, BUFF_AREA_COLOR),
+                        }
+                    }
+                }
+                // What the towers already on the map would do to it
+                let mut incoming = sources.clone();
+                incoming.push(SideEffectSource::new(pos, Entity::PLACEHOLDER, tower));
+                let text = modifiers_at(tower, pos, &incoming)
+                    .iter()
+                    .map(Modifier::description)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if !text.is_empty() {
+                    labels.push(((pos.0, pos.1 + 1), text, TEXT_COLOR));
+                }
+            }
+            Preview::Card(_) => {
+                // How much of its DPS the tower would keep on each tile
+                for pos in map_tiles(&map).filter(|pos| map.is_valid_placement(*pos)) {
+                    let modifiers = modifiers_at(tower, pos, &sources);
+                    if modifiers.is_empty() {
+                        continue;
+                    }
+                    let (mut dps, mut overheats) = (1.0, false);
+                    for modifier in modifiers.iter() {
+                        match modifier.kind {
+                            ModifierKind::Damage | ModifierKind::Rate => {
+                                dps *= 1.0 + modifier.magnitude / 100.0
+                            }
+                            ModifierKind::Overheat => overheats = true,
+                            ModifierKind::Range => {}
+                        }
+                    }
+                    let change = (dps - 1.0) * 100.0;
+                    let alpha = (change.abs() / 100.0).clamp(0.15, 0.6);
+                    if change > 0.0 {
+                        tint(pos, Color::srgba(0.2, 1.0, 0.2, alpha));
+                    } else {
+                        tint(pos, Color::srgba(1.0, 0.2, 0.2, alpha));
+                    }
+                    let mut text = if change.abs() >= 0.5 {
+                        format!("{:+.0}%", change)
+                    } else {
+                        String::new()
+                    };
+                    if overheats {
+                        if !text.is_empty() {
+                            text.push('\n');
+                        }
+                        text.push_str("heat");
+                    }
+                    labels.push((pos, text, TEXT_COLOR));
+                }
+            }
+        }
+        for (pos, text, color) in labels {
+            parent.spawn(Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font: game_assets.font.clone(),
+                        font_size: 9.0,
+                        color,
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                transform: Transform::from_xyz(pos.0 as f32 * 32.0, pos.1 as f32 * 32.0, 8.0),
+                ..Default::default()
+            });
+        }
+        // Enemies near the tower are sped up or slowed down
+        if let Preview::Placing(_, pos) = preview {
+            let auras = [
+                tower
+                    .debuffs()
+                    .any(|debuff| matches!(debuff, Debuff::MoveSpeedUp(_)))
+                    .then_some(AREA_COLOR),
+                matches!(tower.buff, Some(Buff::SlowAura(_))).then_some(BUFF_AREA_COLOR),
+            ];
+            for color in auras.into_iter().flatten() {
+                parent.spawn(MaterialMesh2dBundle {
+                    mesh: meshes.add(Circle::new(SPEED_UP_RADIUS)).into(),
+                    material: materials.add(ColorMaterial::from(color)),
+                    transform: Transform::from_xyz(pos.0 as f32 * 32.0, pos.1 as f32 * 32.0, 3.6),
+                    ..Default::default()
+                });
+            }
+        }
+    });
+}
+
+/// Every tile on the map.
+fn map_tiles(map: &Map) -> impl Iterator<Item = (i8, i8)> + '_ {
+    (0..map.height as i8).flat_map(move |y| (0..map.width as i8).map(move |x| (x, y)))
+}
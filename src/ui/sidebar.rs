@@ -1,15 +1,18 @@
 use bevy::prelude::*;
 
 use crate::{
+    actions::PlayerAction,
+    gameplay::{GameManager, WaveState},
     grid::Map,
     state::loading::GameAssets,
-    tower::{
-        laser::{spawn_laser_beam, Laser},
-        Tower, TowerType,
-    },
+    tower::{charge_shot::ChargeShot, rarity::Rarity, sniper::Sniper, Tower, TowerType, MAX_LEVEL},
 };
 
-use super::{constants::*, UiData};
+use super::{
+    constants::*,
+    inventory::{rarity_color, side_effect_text},
+    UiData,
+};
 
 #[derive(Component)]
 pub struct SidebarRoot;
@@ -17,15 +20,104 @@ pub struct SidebarRoot;
 #[derive(Component)]
 pub struct RotationButton;
 
+#[derive(Component)]
+pub struct UpgradeButton;
+
+#[derive(Component)]
+pub struct SellButton;
+
+/// A line of text about the selected tower.
+fn spawn_stat(parent: &mut ChildBuilder, font: &Handle<Font>, text: String) {
+    spawn_colored_stat(parent, font, text, TEXT_COLOR);
+}
+
+fn spawn_colored_stat(parent: &mut ChildBuilder, font: &Handle<Font>, text: String, color: Color) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Px(30.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color,
+                    },
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    text: String,
+    color: Color,
+    marker: impl Component,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Px(50.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Px(50.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(marker)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            text,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 20.0,
+                                color,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
 pub fn draw_sidebar(
     mut commands: Commands,
     query: Query<Entity, With<SidebarRoot>>,
-    towers: Query<&Tower>,
+    towers: Query<(&Tower, Option<&ChargeShot>, Option<&Sniper>)>,
     ui_data: Res<UiData>,
     map: Res<Map>,
+    game_manager: Res<GameManager>,
     game_assets: Res<GameAssets>,
+    mut upgrades_shown: Local<Option<(u32, bool)>>,
 ) {
-    if ui_data.is_changed() {
+    // The upgrade button depends on the points left and whether a wave is in play
+    let upgrades = (
+        game_manager.upgrade_points,
+        matches!(game_manager.wave_state, WaveState::Waiting),
+    );
+    if ui_data.is_changed() || *upgrades_shown != Some(upgrades) {
+        *upgrades_shown = Some(upgrades);
         // Remove old sidebar
         for entity in query.iter() {
             commands.entity(entity).despawn_recursive();
@@ -51,7 +143,8 @@ pub fn draw_sidebar(
                 if let Some(grid_pos) = ui_data.selected_pos {
                     if let Some(entity) = map.placements.get(&grid_pos) {
                         // Tower is selected
-                        let tower = towers.get(*entity).expect("Tower entity not found");
+                        let (tower, charge_shot, sniper) =
+                            towers.get(*entity).expect("Tower entity not found");
                         // Display tower name
                         parent
                             .spawn(NodeBundle {
@@ -77,35 +170,80 @@ pub fn draw_sidebar(
                                     ..Default::default()
                                 });
                             });
-                        // Display tower DPS
-                        parent
-                            .spawn(NodeBundle {
-                                style: Style {
-                                    width: Val::Percent(100.0),
-                                    height: Val::Px(30.0),
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            })
-                            .with_children(|parent| {
-                                parent.spawn(TextBundle {
-                                    text: Text::from_section(
-                                        format!("DPS: {:.2}", tower.damage * tower.rate),
-                                        TextStyle {
-                                            font: game_assets.font.clone(),
-                                            font_size: 20.0,
-                                            color: TEXT_COLOR,
-                                        },
-                                    ),
-                                    ..Default::default()
-                                });
-                            });
-                        // Display tower debuff
+                        // Display tower stats
+                        let font = &game_assets.font;
+                        if tower.rarity != Rarity::Common {
+                            spawn_colored_stat(
+                                parent,
+                                font,
+                                format!("Rarity: {}", tower.rarity.name()),
+                                rarity_color(tower.rarity),
+                            );
+                        }
+                        if !matches!(tower.variant, TowerType::Jammer) {
+                            spawn_stat(
+                                parent,
+                                font,
+                                format!("Level: {}/{}", tower.level, MAX_LEVEL),
+                            );
+                        }
+                        spawn_stat(
+                            parent,
+                            font,
+                            format!(
+                                "Damage: {:.2} (base {:.2})",
+                                tower.damage, tower.base_damage
+                            ),
+                        );
+                        spawn_stat(
+                            parent,
+                            font,
+                            format!("Rate: {:.2}/s (base {:.2})", tower.rate, tower.base_rate),
+                        );
+                        spawn_stat(
+                            parent,
+                            font,
+                            format!("DPS: {:.2}", tower.damage * tower.rate),
+                        );
+                        let range = charge_shot
+                            .map(|charge_shot| charge_shot.range)
+                            .or(sniper.map(|sniper| sniper.range));
+                        if let Some(range) = range {
+                            spawn_stat(parent, font, format!("Range: {:.1} tiles", range));
+                        }
+                        // Display what the towers around it are doing to it
+                        for modifier in tower.modifiers.iter() {
+                            let source = if modifier.source == *entity {
+                                "Itself".to_string()
+                            } else {
+                                let name = towers
+                                    .get(modifier.source)
+                                    .map_or("Tower", |(source, ..)| source.variant.name());
+                                let pos = map
+                                    .placements
+                                    .iter()
+                                    .find(|(_, placed)| **placed == modifier.source)
+                                    .map(|(pos, _)| *pos);
+                                match pos {
+                                    Some((x, y)) => format!("{} at {},{}", name, x, y),
+                                    None => name.to_string(),
+                                }
+                            };
+                            let color = if modifier.magnitude > 0.0 { GREEN } else { RED };
+                            spawn_colored_stat(
+                                parent,
+                                font,
+                                format!("{}: {}", source, modifier.description()),
+                                color,
+                            );
+                        }
+                        // Display tower debuffs, with more room for rarer towers
+                        let height = 120.0 + 80.0 * tower.extra_debuffs.len() as f32;
                         parent
                             .spawn(NodeBundle {
                                 style: Style {
                                     width: Val::Percent(100.0),
-                                    height: Val::Px(120.0),
+                                    height: Val::Px(height),
                                     ..Default::default()
                                 },
                                 ..Default::default()
@@ -113,7 +251,7 @@ pub fn draw_sidebar(
                             .with_children(|parent| {
                                 parent.spawn(TextBundle {
                                     text: Text::from_section(
-                                        format!("Side effect: {}", tower.debuff.description()),
+                                        side_effect_text(tower),
                                         TextStyle {
                                             font: game_assets.font.clone(),
                                             font_size: 20.0,
@@ -122,51 +260,73 @@ pub fn draw_sidebar(
                                     ),
                                     style: Style {
                                         max_width: Val::Px(200.0),
-                                        max_height: Val::Px(120.0),
+                                        max_height: Val::Px(height),
                                         ..Default::default()
                                     },
                                     ..Default::default()
                                 });
                             });
-                        // If tower is a laser, show a button to toggle its rotation
-                        if let TowerType::Laser = tower.variant {
+                        // Display tower buff
+                        if let Some(buff) = &tower.buff {
                             parent
                                 .spawn(NodeBundle {
                                     style: Style {
                                         width: Val::Percent(100.0),
-                                        height: Val::Px(50.0),
+                                        height: Val::Px(100.0),
                                         ..Default::default()
                                     },
                                     ..Default::default()
                                 })
                                 .with_children(|parent| {
-                                    parent
-                                        .spawn(ButtonBundle {
-                                            style: Style {
-                                                width: Val::Percent(100.0),
-                                                height: Val::Px(50.0),
-                                                justify_content: JustifyContent::Center,
-                                                align_items: AlignItems::Center,
-                                                ..Default::default()
+                                    parent.spawn(TextBundle {
+                                        text: Text::from_section(
+                                            format!("Bonus: {}", buff.description()),
+                                            TextStyle {
+                                                font: game_assets.font.clone(),
+                                                font_size: 20.0,
+                                                color: GREEN,
                                             },
+                                        ),
+                                        style: Style {
+                                            max_width: Val::Px(200.0),
+                                            max_height: Val::Px(100.0),
                                             ..Default::default()
-                                        })
-                                        .insert(RotationButton)
-                                        .with_children(|parent| {
-                                            parent.spawn(TextBundle {
-                                                text: Text::from_section(
-                                                    "Toggle rotation",
-                                                    TextStyle {
-                                                        font: game_assets.font.clone(),
-                                                        font_size: 20.0,
-                                                        color: BUTTON_TEXT_COLOR,
-                                                    },
-                                                ),
-                                                ..Default::default()
-                                            });
-                                        });
+                                        },
+                                        ..Default::default()
+                                    });
                                 });
                         }
+                        // Between waves, show a button to upgrade the tower
+                        if let (Some(cost), (points, true)) = (tower.upgrade_cost(), upgrades) {
+                            let color = if points >= cost {
+                                BUTTON_TEXT_COLOR
+                            } else {
+                                RED
+                            };
+                            spawn_stat(parent, font, format!("Upgrade points: {}", points));
+                            spawn_button(
+                                parent,
+                                font,
+                                format!("Upgrade (costs {})", cost),
+                                color,
+                                UpgradeButton,
+                            );
+                        }
+                        let sell_text = match tower.sell_refund() {
+                            0 => "Sell".to_string(),
+                            refund => format!("Sell (+{} points)", refund),
+                        };
+                        spawn_button(parent, font, sell_text, BUTTON_TEXT_COLOR, SellButton);
+                        // If tower is a laser, show a button to toggle its rotation
+                        if let TowerType::Laser = tower.variant {
+                            spawn_button(
+                                parent,
+                                font,
+                                "Toggle rotation".to_string(),
+                                BUTTON_TEXT_COLOR,
+                                RotationButton,
+                            );
+                        }
                     }
                 } else {
                     parent.spawn(TextBundle {
@@ -186,42 +346,70 @@ pub fn draw_sidebar(
 }
 
 pub fn handle_toggle_rotation_button(
-    mut commands: Commands,
     mut query: Query<
         (&Interaction, &mut BackgroundColor),
         (With<RotationButton>, Changed<Interaction>),
     >,
-    mut lasers: Query<(Entity, &mut Transform, &mut Laser, &Children)>,
     ui_data: Res<UiData>,
-    map: Res<Map>,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<ColorMaterial>>,
+    mut actions: EventWriter<PlayerAction>,
 ) {
     for (interaction, mut background_color) in query.iter_mut() {
         match interaction {
             Interaction::Pressed => {
                 if let Some(grid_pos) = ui_data.selected_pos {
-                    if let Some(entity) = map.placements.get(&grid_pos) {
-                        let (entity, mut transform, mut laser, children) =
-                            lasers.get_mut(*entity).unwrap();
-                        // Change laser direction
-                        transform.rotation *= Quat::from_rotation_z(-std::f32::consts::PI / 2.0);
-                        laser.toggle_direction();
-                        // Remove and redraw beam
-                        for child in children.iter() {
-                            commands.entity(*child).despawn_recursive();
-                        }
-                        commands.entity(entity).with_children(|parent| {
-                            spawn_laser_beam(
-                                parent,
-                                grid_pos,
-                                laser.direction,
-                                meshes,
-                                materials,
-                                &map,
-                            )
-                        });
-                    }
+                    actions.send(PlayerAction::RotateLaser { pos: grid_pos });
+                }
+                break;
+            }
+            Interaction::Hovered => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR_HOVER;
+            }
+            Interaction::None => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR;
+            }
+        }
+    }
+}
+
+pub fn handle_upgrade_button(
+    mut query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (With<UpgradeButton>, Changed<Interaction>),
+    >,
+    ui_data: Res<UiData>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    for (interaction, mut background_color) in query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                if let Some(grid_pos) = ui_data.selected_pos {
+                    actions.send(PlayerAction::UpgradeTower { pos: grid_pos });
+                }
+                break;
+            }
+            Interaction::Hovered => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR_HOVER;
+            }
+            Interaction::None => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR;
+            }
+        }
+    }
+}
+
+pub fn handle_sell_button(
+    mut query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (With<SellButton>, Changed<Interaction>),
+    >,
+    ui_data: Res<UiData>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    for (interaction, mut background_color) in query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                if let Some(grid_pos) = ui_data.selected_pos {
+                    actions.send(PlayerAction::SellTower { pos: grid_pos });
                 }
                 break;
             }
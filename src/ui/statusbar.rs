@@ -1,7 +1,9 @@
 use bevy::prelude::*;
 
 use crate::{
+    actions::PlayerAction,
     gameplay::{GameManager, WaveState},
+    settings::SpeedPreference,
     state::loading::GameAssets,
 };
 
@@ -15,6 +17,8 @@ pub struct NormalSpeedButton;
 #[derive(Component)]
 pub struct FastSpeedButton;
 #[derive(Component)]
+pub struct AutoStartButton;
+#[derive(Component)]
 pub struct StatusBarScore;
 #[derive(Component)]
 pub struct StatusBarLives;
@@ -101,6 +105,32 @@ pub fn draw_status_bar(mut commands: Commands, game_assets: Res<GameAssets>) {
                             });
                         })
                         .insert(FastSpeedButton);
+                    parent
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(80.0),
+                                height: Val::Px(50.0),
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::Center,
+                                padding: UiRect::all(Val::Px(10.0)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle {
+                                text: Text::from_section(
+                                    "Auto",
+                                    TextStyle {
+                                        font: game_assets.font.clone(),
+                                        font_size: 30.0,
+                                        color: TEXT_COLOR,
+                                    },
+                                ),
+                                ..Default::default()
+                            });
+                        })
+                        .insert(AutoStartButton);
                 });
             parent
                 .spawn(NodeBundle {
@@ -184,15 +214,18 @@ pub fn draw_status_bar(mut commands: Commands, game_assets: Res<GameAssets>) {
 }
 
 pub fn handle_normal_speed_button(
-    mut query: Query<(&Interaction, &mut BackgroundColor), With<NormalSpeedButton>>,
-    mut game_speed: ResMut<GameSpeed>,
-    mut time: ResMut<Time<Virtual>>,
+    mut query: Query<(Ref<Interaction>, &mut BackgroundColor), With<NormalSpeedButton>>,
+    game_speed: Res<GameSpeed>,
+    mut speed_preference: ResMut<SpeedPreference>,
+    mut actions: EventWriter<PlayerAction>,
 ) {
     for (interaction, mut background_color) in query.iter_mut() {
-        match interaction {
+        match *interaction {
             Interaction::Pressed => {
-                game_speed.0 = false;
-                time.set_relative_speed(1.0);
+                if interaction.is_changed() {
+                    actions.send(PlayerAction::SetSpeed { fast: false });
+                    speed_preference.0 = false;
+                }
                 background_color.0 = BUTTON_BACKGROUND_COLOR_ACTIVE;
             }
             Interaction::Hovered => {
@@ -210,15 +243,18 @@ pub fn handle_normal_speed_button(
 }
 
 pub fn handle_fast_speed_button(
-    mut query: Query<(&Interaction, &mut BackgroundColor), With<FastSpeedButton>>,
-    mut game_speed: ResMut<GameSpeed>,
-    mut time: ResMut<Time<Virtual>>,
+    mut query: Query<(Ref<Interaction>, &mut BackgroundColor), With<FastSpeedButton>>,
+    game_speed: Res<GameSpeed>,
+    mut speed_preference: ResMut<SpeedPreference>,
+    mut actions: EventWriter<PlayerAction>,
 ) {
     for (interaction, mut background_color) in query.iter_mut() {
-        match interaction {
+        match *interaction {
             Interaction::Pressed => {
-                game_speed.0 = true;
-                time.set_relative_speed(3.0);
+                if interaction.is_changed() {
+                    actions.send(PlayerAction::SetSpeed { fast: true });
+                    speed_preference.0 = true;
+                }
                 background_color.0 = BUTTON_BACKGROUND_COLOR_ACTIVE;
             }
             Interaction::Hovered => {
@@ -235,6 +271,35 @@ pub fn handle_fast_speed_button(
     }
 }
 
+/// Toggle the countdown that starts the next wave automatically.
+pub fn handle_auto_start_button(
+    mut query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (With<AutoStartButton>, Changed<Interaction>),
+    >,
+    game_manager: Res<GameManager>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    for (interaction, mut background_color) in query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                actions.send(PlayerAction::ToggleAutoStart);
+                background_color.0 = BUTTON_BACKGROUND_COLOR_PRESSED;
+            }
+            Interaction::Hovered => {
+                background_color.0 = BUTTON_BACKGROUND_COLOR_HOVER;
+            }
+            Interaction::None => {
+                background_color.0 = if game_manager.countdown.is_some() {
+                    BUTTON_BACKGROUND_COLOR_ACTIVE
+                } else {
+                    BUTTON_BACKGROUND_COLOR
+                };
+            }
+        }
+    }
+}
+
 pub fn update_status_bar_text(
     mut query: Query<&mut Text, With<StatusBarText>>,
     ui_state: Res<UiStateResource>,
@@ -242,14 +307,35 @@ pub fn update_status_bar_text(
 ) {
     if let Ok(mut text) = query.get_single_mut() {
         match game_manager.wave_state {
-            WaveState::Spawning(_) | WaveState::Finished => {
-                text.sections[0].value = format!("Wave: {}", game_manager.current_wave);
+            WaveState::Spawning | WaveState::Finished => {
+                // List every wave that's still spawning if they overlap
+                let waves = if game_manager.active_waves.len() > 1 {
+                    let numbers: Vec<String> = game_manager
+                        .active_waves
+                        .iter()
+                        .map(|active| active.number.to_string())
+                        .collect();
+                    numbers.join(", ")
+                } else {
+                    game_manager.current_wave.to_string()
+                };
+                text.sections[0].value = format!(
+                    "Wave: {}. Press SPACE to call the next wave early for a bonus.",
+                    waves
+                );
             }
             WaveState::Waiting => match ui_state.state {
                 UiState::Normal => {
-                    text.sections[0].value =
-                        "Once you have placed your towers, press SPACE to start the next wave."
-                            .to_string();
+                    text.sections[0].value = match &game_manager.countdown {
+                        Some(countdown) => format!(
+                            "Next wave in {:.0}s. Press SPACE to start it now.",
+                            countdown.remaining_secs().ceil()
+                        ),
+                        None => {
+                            "Once you have placed your towers, press SPACE to start the next wave."
+                                .to_string()
+                        }
+                    };
                 }
                 UiState::PlacingTower(_) => {
                     text.sections[0].value =
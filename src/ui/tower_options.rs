@@ -2,15 +2,12 @@ use bevy::prelude::*;
 use bevy_kira_audio::{AudioChannel, AudioControl};
 
 use crate::{
+    actions::PlayerAction,
     audio::{AudioAssets, SoundChannel},
     tower::Tower,
 };
 
-use super::{
-    constants::*,
-    inventory::{draw_tower_card, Inventory},
-    UiState, UiStateResource,
-};
+use super::{constants::*, inventory::draw_tower_card, UiState, UiStateResource};
 
 #[derive(Component)]
 pub struct TowerOption {
@@ -82,11 +79,9 @@ pub fn present_tower_options(mut commands: Commands, font: Handle<Font>, towers:
 }
 
 pub fn handle_tower_options(
-    mut commands: Commands,
-    mut ui_state: ResMut<UiStateResource>,
+    ui_state: Res<UiStateResource>,
     mut query: Query<(&TowerOption, &Interaction, &mut BackgroundColor), Changed<Interaction>>,
-    root: Query<Entity, With<TowerOptionsRoot>>,
-    mut inventory: ResMut<Inventory>,
+    mut actions: EventWriter<PlayerAction>,
     sound_channel: Res<AudioChannel<SoundChannel>>,
     audio_assets: Res<AudioAssets>,
 ) {
@@ -94,14 +89,9 @@ pub fn handle_tower_options(
         match interaction {
             Interaction::Pressed => {
                 sound_channel.play(audio_assets.blip2.clone());
-                if let UiState::PickingTower(ref mut options) = ui_state.state {
-                    let tower = options.remove(tower_option.index);
-                    inventory.towers.push(tower);
-                }
-                for entity in root.iter() {
-                    commands.entity(entity).despawn_recursive();
-                }
-                ui_state.state = UiState::Normal;
+                actions.send(PlayerAction::PickTower {
+                    index: tower_option.index,
+                });
             }
             Interaction::Hovered => {
                 background_color.0 = CARD_BACKGROUND_COLOR_HOVER;
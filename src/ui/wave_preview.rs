@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use crate::{enemies::Enemy, gameplay::GameManager, state::loading::GameAssets, waves::WaveList};
+
+use super::constants::*;
+
+#[derive(Component)]
+pub struct WavePreviewRoot;
+
+/// Show what the next wave contains, so the player can decide which towers to pick and whether
+/// to call it early.
+pub fn draw_wave_preview(
+    mut commands: Commands,
+    query: Query<Entity, With<WavePreviewRoot>>,
+    game_manager: Res<GameManager>,
+    game_assets: Res<GameAssets>,
+    mut wave_events: EventReader<AssetEvent<WaveList>>,
+    mut shown: Local<Option<usize>>,
+) {
+    // Only redraw when the upcoming wave changes or the waves file is reloaded, or when the
+    // preview has been cleared away by a new run starting
+    let number = game_manager.upcoming_wave();
+    let reloaded = wave_events.read().count() > 0;
+    if *shown == Some(number) && !reloaded && !query.is_empty() {
+        return;
+    }
+    // Remove old preview
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(wave) = game_manager.wave(number) else {
+        *shown = None;
+        return;
+    };
+    *shown = Some(number);
+    // Draw new preview
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(250.0),
+                height: Val::Auto,
+                position_type: PositionType::Absolute,
+                right: Val::Px(0.0),
+                top: Val::Px(50.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            background_color: CARD_BACKGROUND_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(WavePreviewRoot)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    format!("Next wave: {}", number),
+                    TextStyle {
+                        font: game_assets.font.clone(),
+                        font_size: 20.0,
+                        color: TEXT_COLOR,
+                    },
+                ),
+                style: Style {
+                    margin: UiRect::bottom(Val::Px(10.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            if wave.segments.is_empty() {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        "No enemies",
+                        TextStyle {
+                            font: game_assets.font.clone(),
+                            font_size: 20.0,
+                            color: CARD_TEXT_COLOR,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            }
+            for segment in wave.segments.iter() {
+                let enemy = segment.enemy(
+                    (0, 0),
+                    Vec::new(),
+                    game_manager.health_multiplier,
+                    game_manager.speed_multiplier,
+                );
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Px(40.0),
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(ImageBundle {
+                            image: UiImage::new(game_assets.enemy_texture(segment.enemy_type)),
+                            style: Style {
+                                width: Val::Px(32.0),
+                                height: Val::Px(32.0),
+                                margin: UiRect::right(Val::Px(10.0)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        });
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                enemy_summary(segment.count, &enemy),
+                                TextStyle {
+                                    font: game_assets.font.clone(),
+                                    font_size: 18.0,
+                                    color: CARD_TEXT_COLOR,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+/// One line about a segment's enemies: how many there are, their stats and their traits.
+fn enemy_summary(count: usize, enemy: &Enemy) -> String {
+    let mut summary = format!(
+        "x{}  HP: {:.0}  Speed: {:.0}",
+        count, enemy.max_health, enemy.move_speed
+    );
+    let armour = enemy.variant.armour();
+    if armour > 0.0 {
+        summary.push_str(&format!("  Armour: {}", armour));
+    }
+    if enemy.max_shield > 0.0 {
+        summary.push_str(&format!("  Shield: {:.0}", enemy.max_shield));
+    }
+    let regeneration = enemy.variant.regeneration();
+    if regeneration > 0.0 {
+        summary.push_str(&format!("  Regen: {:.1}%/s", regeneration * 100.0));
+    }
+    summary
+}
@@ -0,0 +1,104 @@
+//! Checking a submitted score by playing its replay again headlessly. The replay's map and waves
+//! aren't trusted, so the run is played on maps and waves the caller provides.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{
+    actions::PlayerAction,
+    daily,
+    gameplay::RunMode,
+    grid::map_file::MapData,
+    replay::{self, Replay},
+    simulation::{self, SimulationError, SimulationReport},
+    storage::StorageError,
+    waves::WaveList,
+};
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error(transparent)]
+    Replay(#[from] StorageError),
+    #[error(transparent)]
+    Simulation(#[from] SimulationError),
+    #[error("daily challenge run was played with seed {seed} instead of the day's seed")]
+    WrongSeed { seed: u64 },
+    #[error("claimed a score of {claimed} but the replay scores {actual}")]
+    ScoreMismatch { claimed: u32, actual: u32 },
+    #[error("claimed to reach wave {claimed} but the replay reaches wave {actual}")]
+    WaveMismatch { claimed: usize, actual: usize },
+    #[error("the waves were edited at tick {tick}, so the run wasn't played on the given waves")]
+    WavesEdited { tick: u64 },
+}
+
+/// Play a replay on the given map and waves, and check it gets the score and wave it claims.
+/// Runs whose waves were edited partway through can't have been played on the given waves.
+pub fn verify(
+    replay: &Replay,
+    map: &MapData,
+    waves: &WaveList,
+) -> Result<SimulationReport, VerifyError> {
+    if let RunMode::Daily(day) = replay.mode {
+        if replay.seed != daily::seed(day) {
+            return Err(VerifyError::WrongSeed { seed: replay.seed });
+        }
+    }
+    let edit = replay
+        .actions
+        .iter()
+        .find(|recorded| matches!(recorded.action, PlayerAction::LoadWaves { .. }));
+    if let Some(edit) = edit {
+        return Err(VerifyError::WavesEdited { tick: edit.tick });
+    }
+    let report = simulation::replay(replay, map, waves)?;
+    if report.score != replay.score {
+        return Err(VerifyError::ScoreMismatch {
+            claimed: replay.score,
+            actual: report.score,
+        });
+    }
+    if report.wave_reached != replay.wave_reached {
+        return Err(VerifyError::WaveMismatch {
+            claimed: replay.wave_reached,
+            actual: report.wave_reached,
+        });
+    }
+    Ok(report)
+}
+
+/// Verify a replay file against a map in `assets_dir`, played with the map's waves.
+pub fn verify_file(
+    replay_path: &Path,
+    assets_dir: &Path,
+    map_path: &str,
+) -> Result<SimulationReport, VerifyError> {
+    let replay = replay::load(replay_path)?;
+    let (map, waves) = simulation::load_map_and_waves(assets_dir, map_path, None)?;
+    verify(&replay, &map, &waves)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn recorded_run_verifies_until_tampered_with() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let scenario = simulation::load_scenario(&root.join("simulations/offers.sim.ron")).unwrap();
+        let (report, mut replay) = simulation::record(&scenario, &root.join("assets")).unwrap();
+        let (map, waves) = (replay.map.clone(), replay.waves.clone());
+
+        let verified = verify(&replay, &map, &waves).unwrap();
+        assert_eq!(verified.score, report.score);
+        assert_eq!(verified.wave_reached, report.wave_reached);
+
+        replay.score += 1;
+        assert!(matches!(
+            verify(&replay, &map, &waves),
+            Err(VerifyError::ScoreMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,383 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    actions::PlayerAction,
+    enemies::{Enemy, EnemyVariant},
+    gameplay::{GameManager, WaveState},
+    replay::Recording,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveSegment {
+    pub enemy_type: EnemyVariant,
+    pub count: usize,
+    pub spawn_rate: f32,
+    /// Index of the map spawn that enemies in this segment enter from.
+    #[serde(default)]
+    pub spawn: usize,
+    /// Seconds to wait before the first enemy of this segment spawns.
+    #[serde(default)]
+    pub delay: f32,
+    /// Replaces the enemy type's base health.
+    #[serde(default)]
+    pub health: Option<f32>,
+    /// Replaces the enemy type's base move speed.
+    #[serde(default)]
+    pub speed: Option<f32>,
+}
+impl WaveSegment {
+    /// Create one of this segment's enemies, with the stats overrides and multipliers applied.
+    pub fn enemy(
+        &self,
+        grid_pos: (i8, i8),
+        path: Vec<(i8, i8)>,
+        health_multiplier: f32,
+        speed_multiplier: f32,
+    ) -> Enemy {
+        let mut enemy = Enemy::new(
+            self.enemy_type,
+            grid_pos,
+            path,
+            health_multiplier,
+            speed_multiplier,
+        );
+        if let Some(health) = self.health {
+            enemy.max_health = health * health_multiplier;
+            enemy.current_health = enemy.max_health;
+            enemy.max_shield = enemy.max_health * self.enemy_type.shield();
+            enemy.current_shield = enemy.max_shield;
+        }
+        if let Some(speed) = self.speed {
+            enemy.move_speed = speed * speed_multiplier;
+        }
+        enemy
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wave {
+    pub segments: Vec<WaveSegment>,
+}
+
+/// A list of waves as it is stored in a `.waves.ron` file.
+#[derive(Asset, TypePath, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveList {
+    pub waves: Vec<Wave>,
+    /// How waves are generated once `waves` runs out.
+    #[serde(default)]
+    pub endless: EndlessSettings,
+}
+impl WaveList {
+    pub fn validate(&self) -> Result<(), WaveError> {
+        if self.endless.budget <= 0.0 || self.endless.budget_growth < 1.0 {
+            return Err(WaveError::InvalidBudget {
+                budget: self.endless.budget,
+                growth: self.endless.budget_growth,
+            });
+        }
+        for (wave, w) in self.waves.iter().enumerate() {
+            for (segment, s) in w.segments.iter().enumerate() {
+                if s.spawn_rate <= 0.0 {
+                    return Err(WaveError::InvalidSpawnRate {
+                        wave,
+                        segment,
+                        spawn_rate: s.spawn_rate,
+                    });
+                }
+                if s.delay < 0.0 {
+                    return Err(WaveError::NegativeDelay {
+                        wave,
+                        segment,
+                        delay: s.delay,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every enemy type, cheapest first.
+const ENEMY_VARIANTS: [EnemyVariant; 7] = [
+    EnemyVariant::Weak,
+    EnemyVariant::Normal,
+    EnemyVariant::Fast,
+    EnemyVariant::Strong,
+    EnemyVariant::StrongFast,
+    EnemyVariant::Boss,
+    EnemyVariant::UltraBoss,
+];
+
+/// Settings for generating endless mode waves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndlessSettings {
+    /// Points to spend on the first generated wave, using [`EnemyVariant::points`] as the cost of
+    /// each enemy.
+    pub budget: f32,
+    /// How much the budget is multiplied by for each wave after that.
+    pub budget_growth: f32,
+    /// Seed for the generator. If not set, the run's seed is used.
+    pub seed: Option<u64>,
+}
+impl Default for EndlessSettings {
+    fn default() -> Self {
+        Self {
+            budget: 300.0,
+            budget_growth: 1.12,
+            seed: None,
+        }
+    }
+}
+
+/// Composes endless mode waves by spending a budget of points on enemies. The same seed always
+/// generates the same waves.
+#[derive(Debug, Clone)]
+pub struct WaveGenerator {
+    pub settings: EndlessSettings,
+    pub seed: u64,
+}
+impl WaveGenerator {
+    pub fn new(settings: EndlessSettings, run_seed: u64) -> Self {
+        let seed = settings.seed.unwrap_or(run_seed);
+        Self { settings, seed }
+    }
+
+    /// Generate the `index`th endless wave, counting from 0. Segments are spread over `spawns`
+    /// spawn points.
+    pub fn generate(&self, index: usize, spawns: usize) -> Wave {
+        // Each wave gets its own rng so that a wave doesn't depend on the ones before it. Unlike
+        // `StdRng`, ChaCha12 is guaranteed to give the same numbers on every platform and version,
+        // so replays of endless runs get the same waves when they're verified
+        let mut rng = ChaCha12Rng::seed_from_u64(
+            self.seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(index as u64),
+        );
+        let budget = self.settings.budget * self.settings.budget_growth.powi(index as i32);
+
+        // Only enemies that fit into the budget a few times over can appear, so stronger enemies
+        // are introduced as the budget grows
+        let available: Vec<EnemyVariant> = ENEMY_VARIANTS
+            .into_iter()
+            .filter(|variant| variant.points() as f32 * 4.0 <= budget)
+            .collect();
+        let newest = *available.last().unwrap_or(&EnemyVariant::Weak);
+
+        // The newest threat gets a segment of its own at the end of the wave, and the rest of the
+        // budget is split between a random mix of everything else that fits into its share. The
+        // threat always gets at least one enemy, even if the budget is too small for it.
+        let threat_budget = (budget * rng.gen_range(0.2..0.4)).max(newest.points() as f32);
+        let segment_count = rng.gen_range(2..=4);
+        let share = (budget - threat_budget).max(0.0) / segment_count as f32;
+        let affordable: Vec<EnemyVariant> = available
+            .iter()
+            .copied()
+            .filter(|variant| variant.points() as f32 <= share)
+            .collect();
+        let mut segments = Vec::new();
+        for _ in 0..segment_count {
+            if let Some(variant) = affordable.choose(&mut rng) {
+                segments.push(self.segment(&mut rng, *variant, share, spawns));
+            }
+        }
+        let mut threat = self.segment(&mut rng, newest, threat_budget, spawns);
+        threat.delay = 3.0;
+        segments.push(threat);
+        Wave { segments }
+    }
+
+    fn segment(
+        &self,
+        rng: &mut ChaCha12Rng,
+        variant: EnemyVariant,
+        budget: f32,
+        spawns: usize,
+    ) -> WaveSegment {
+        let points = variant.points() as f32;
+        // Cheap enemies come in quick streams, expensive ones are spaced out
+        let spawn_rate = (4.0 / points.sqrt()).clamp(0.2, 3.0) * rng.gen_range(0.8..1.2);
+        WaveSegment {
+            enemy_type: variant,
+            count: ((budget / points) as usize).max(1),
+            spawn_rate,
+            spawn: rng.gen_range(0..spawns.max(1)),
+            delay: rng.gen_range(0.0..1.5),
+            health: None,
+            speed: None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WaveError {
+    #[error(
+        "endless budget is {budget} with growth {growth}, \
+         the budget must be above 0 and the growth at least 1"
+    )]
+    InvalidBudget { budget: f32, growth: f32 },
+    #[error("wave {wave} segment {segment} has spawn rate {spawn_rate}, it must be above 0")]
+    InvalidSpawnRate {
+        wave: usize,
+        segment: usize,
+        spawn_rate: f32,
+    },
+    #[error("wave {wave} segment {segment} has a negative delay of {delay}")]
+    NegativeDelay {
+        wave: usize,
+        segment: usize,
+        delay: f32,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum WaveLoadError {
+    #[error("could not read waves file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse waves file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("invalid waves: {0}")]
+    Invalid(#[from] WaveError),
+}
+
+#[derive(Default)]
+pub struct WaveLoader;
+
+impl AssetLoader for WaveLoader {
+    type Asset = WaveList;
+    type Settings = ();
+    type Error = WaveLoadError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<WaveList, WaveLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let waves: WaveList = ron::de::from_bytes(&bytes)?;
+        waves.validate()?;
+        Ok(waves)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+/// Copy the waves into the game whenever the waves file finishes loading or is edited on disk.
+/// Once a recorded run has started, edits are sent as [`PlayerAction::LoadWaves`] instead, so
+/// they're recorded and the replay picks up the new waves at the same tick.
+pub fn reload_waves(
+    mut events: EventReader<AssetEvent<WaveList>>,
+    mut game_manager: ResMut<GameManager>,
+    wave_lists: Res<Assets<WaveList>>,
+    recording: Option<ResMut<Recording>>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    let started =
+        game_manager.current_wave > 0 || !matches!(game_manager.wave_state, WaveState::Waiting);
+    let mut recording = recording.filter(|_| started);
+    for event in events.read() {
+        match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }
+                if *id == game_manager.wave_source.id() =>
+            {
+                let Some(list) = wave_lists.get(*id) else {
+                    continue;
+                };
+                match &mut recording {
+                    Some(recording) => {
+                        recording
+                            .waves
+                            .get_or_insert_with(|| game_manager.wave_list());
+                        actions.send(PlayerAction::LoadWaves {
+                            waves: list.clone(),
+                        });
+                    }
+                    None => game_manager.load_waves(list),
+                }
+                info!("Loaded {} waves", list.waves.len());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(wave: &Wave) -> f32 {
+        wave.segments
+            .iter()
+            .map(|segment| (segment.count as u32 * segment.enemy_type.points()) as f32)
+            .sum()
+    }
+
+    fn strongest(wave: &Wave) -> u32 {
+        wave.segments
+            .iter()
+            .map(|segment| segment.enemy_type.points())
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn same_seed_and_index_give_the_same_wave() {
+        let generator = WaveGenerator::new(EndlessSettings::default(), 42);
+        let again = WaveGenerator::new(EndlessSettings::default(), 42);
+        for index in 0..10 {
+            assert_eq!(generator.generate(index, 2), again.generate(index, 2));
+        }
+        let other = WaveGenerator::new(EndlessSettings::default(), 43);
+        assert_ne!(generator.generate(0, 2), other.generate(0, 2));
+    }
+
+    #[test]
+    fn waves_stay_within_their_budget() {
+        let settings = EndlessSettings::default();
+        for seed in 0..20 {
+            let generator = WaveGenerator::new(settings.clone(), seed);
+            for index in 0..30 {
+                let budget = settings.budget * settings.budget_growth.powi(index as i32);
+                let wave = generator.generate(index, 3);
+                assert!(!wave.segments.is_empty());
+                assert!(
+                    points(&wave) <= budget,
+                    "seed {} wave {} spends {} of {}",
+                    seed,
+                    index,
+                    points(&wave),
+                    budget
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stronger_enemies_appear_as_the_budget_grows() {
+        let settings = EndlessSettings {
+            budget: 10.0,
+            budget_growth: 1.5,
+            seed: None,
+        };
+        for seed in 0..10 {
+            let generator = WaveGenerator::new(settings.clone(), seed);
+            let strength: Vec<u32> = (0..12)
+                .map(|index| strongest(&generator.generate(index, 1)))
+                .collect();
+            assert!(strength.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(strength[0] < strength[11], "{:?}", strength);
+            assert_eq!(strength[11], EnemyVariant::UltraBoss.points());
+        }
+    }
+}